@@ -0,0 +1,54 @@
+//! 将输出文件打包为 zip（`--zip-output`）
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// 把文件打包为 zip，条目路径相对于所有文件的共同上级目录，保留子目录结构
+pub fn save_zip(paths: &[PathBuf], archive_path: &Path) -> Result<()> {
+    let base = common_parent(paths);
+
+    let file = File::create(archive_path)
+        .with_context(|| format!("无法创建文件: {}", archive_path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for path in paths {
+        let relative = path.strip_prefix(&base).unwrap_or(path);
+        // zip 条目统一使用 '/' 分隔
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let data = std::fs::read(path)
+            .with_context(|| format!("无法读取文件: {}", path.display()))?;
+
+        zip.start_file(name, options)
+            .with_context(|| format!("写入 zip 失败: {}", archive_path.display()))?;
+        zip.write_all(&data)
+            .with_context(|| format!("写入 zip 失败: {}", archive_path.display()))?;
+    }
+
+    zip.finish()
+        .with_context(|| format!("写入 zip 失败: {}", archive_path.display()))?;
+    Ok(())
+}
+
+/// 所有文件的共同上级目录
+fn common_parent(paths: &[PathBuf]) -> PathBuf {
+    let mut base = paths
+        .first()
+        .and_then(|path| path.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    while !paths.iter().all(|path| path.starts_with(&base)) {
+        if !base.pop() {
+            break;
+        }
+    }
+    base
+}
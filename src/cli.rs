@@ -0,0 +1,474 @@
+//! 命令行参数定义
+//!
+//! [`Cli`] 中的选项都是可选的，[`Cli::apply`] 只把出现过的选项写入 [`Options`]，
+//! 未出现的保持原值。命令行和 `.xdts2sts.toml` 的覆盖都经过这里解析。
+//!
+//! 开关选项也可以写成 `--strict=false`，`.xdts2sts.toml` 借此关闭命令行上打开的开关。
+
+use crate::options::{
+    parse_cel_equivalence, parse_cut_map, read_name_list, CellWidth, ExplainTarget,
+    FractionalPolicy, HoldStyle, LayerDuration, NormalizeCells, Options, OutputFormat,
+    OverCellPolicy, Retime, UnderflowPolicy,
+};
+use crate::types::SourceFormat;
+use anyhow::{Context, Result};
+use clap::builder::PossibleValue;
+use clap::{Arg, ArgAction, Parser};
+use regex::Regex;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// XDTS/TDTS 转 STS 转换工具
+///
+/// 不带选项传入文件或文件夹（如拖放到程序上）时按默认设置转换。
+///
+/// 单个文件输出到同目录，文件夹输出到程序所在目录的 converted_sts。
+///
+/// 参数也可以写在文本文件中，以 `@文件路径` 传入。
+#[derive(Debug, Parser)]
+#[command(name = "xdts2sts", version, args_override_self = true, mut_args = switch_arg)]
+pub struct Cli {
+    /// 要转换的 .xdts/.tdts 文件或文件夹
+    #[arg(value_name = "路径")]
+    pub inputs: Vec<String>,
+
+    /// 裁剪末尾的空白帧
+    #[arg(long)]
+    pub trim_trailing_blank: Option<bool>,
+    /// 裁剪后额外保留的空白帧数
+    #[arg(long, value_name = "N")]
+    pub trim_margin: Option<u32>,
+    /// 将同一源文件的所有时间表打包为一个 .stsx 容器
+    #[arg(long)]
+    pub container: Option<bool>,
+    /// 仅当源文件比已有输出更新时才重新转换
+    #[arg(long)]
+    pub overwrite_if_newer: Option<bool>,
+    /// 单文件模式下写入前显示预览并确认
+    #[arg(long)]
+    pub confirm: Option<bool>,
+    /// 非整数 cel 值的处理方式
+    #[arg(long, value_name = "round|floor|reject")]
+    pub fractional: Option<FractionalPolicy>,
+    /// 将 cel 编号压缩为从 1 开始的连续编号
+    #[arg(long, value_name = "per-layer|global")]
+    pub normalize_cells: Option<NormalizeCells>,
+    /// 额外输出曝光预览 PNG 的目录
+    #[arg(long, value_name = "目录")]
+    pub preview_png: Option<PathBuf>,
+    /// 源文件大小上限（字节），超过则跳过
+    #[arg(long, value_name = "字节")]
+    pub max_file_size: Option<u64>,
+    /// cel 编号后的延长保持标记，逗号分隔（默认 E）
+    #[arg(long, value_name = "标记,...")]
+    pub hold_markers: Option<String>,
+    /// 额外接受的扩展名（如 json、xdts.json），可多次指定
+    #[arg(long, value_name = "扩展名", action = ArgAction::Append)]
+    pub also_ext: Vec<String>,
+    /// 反转输出的层顺序
+    #[arg(long)]
+    pub reverse_layers: Option<bool>,
+    /// 帧率，用于换算时长（秒）
+    #[arg(long)]
+    pub fps: Option<f64>,
+    /// 编码前将层名称做 Unicode NFC 规范化
+    #[arg(long)]
+    pub normalize_names: Option<bool>,
+    /// 编码后打印输出数据的前 N 字节
+    #[arg(long, value_name = "N")]
+    pub dump_bytes: Option<usize>,
+    /// 层名称无法编码为 Shift-JIS 时报错而不是警告
+    #[arg(long)]
+    pub strict_names: Option<bool>,
+    /// 单个文件的处理时间上限（秒），超时视为失败
+    #[arg(long, value_name = "秒")]
+    pub timeout: Option<f64>,
+    /// 输出格式
+    #[arg(long, value_name = "sts|otcsv|csv|long-csv|json")]
+    pub format: Option<OutputFormat>,
+    /// 源数据中显式空白写入的 cel 值（默认 0）
+    #[arg(long, value_name = "N")]
+    pub null_cell_value: Option<u32>,
+    /// 输出 cel 编号的起始值
+    #[arg(long, value_name = "0|1")]
+    pub cel_base: Option<u32>,
+    /// 非空 cel 额外加上的偏移量，可为负
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    pub cell_offset: Option<i32>,
+    /// 偏移后 cel 编号小于 1 时的处理方式
+    #[arg(long, value_name = "clamp-to-null|error")]
+    pub underflow: Option<UnderflowPolicy>,
+    /// 只解析并校验，不写入任何文件
+    #[arg(long)]
+    pub validate_only: Option<bool>,
+    /// 多时间表的源文件输出到以源文件名命名的子目录
+    #[arg(long)]
+    pub subdir_per_source: Option<bool>,
+    /// STS 帧数据区每个 cell 的字节数（1 最大 255，2 最大 65535）
+    #[arg(long, value_name = "1|2|4")]
+    pub cell_width: Option<CellWidth>,
+    /// 打印 --format json 输出的 JSON Schema 后退出
+    #[arg(long)]
+    pub json_schema: Option<bool>,
+    /// 以 JSON 输出版本和支持的格式后退出
+    #[arg(long)]
+    pub info: Option<bool>,
+    /// 按文件中列出的顺序（每行一个名称）重排层
+    #[arg(long, value_name = "文件")]
+    pub layer_order_from: Option<String>,
+    /// 只保留文件中列出的层（每行一个名称），并按列出的顺序排列
+    #[arg(long, value_name = "文件")]
+    pub keep_layers: Option<String>,
+    /// 文本导出中记录源文件路径、时间表名称和转换时间
+    #[arg(long)]
+    pub embed_provenance: Option<bool>,
+    /// 同一 cut 内时间表时长允许相差的帧数（--validate-only 时检查）
+    #[arg(long, value_name = "N")]
+    pub cut_duration_tolerance: Option<u32>,
+    /// 将同一 cut 内所有时间表的时长统一为其中的最大值
+    #[arg(long)]
+    pub align_cut_durations: Option<bool>,
+    /// 与 --format json 同用：每个源文件只输出时间表概要
+    #[arg(long)]
+    pub metadata_only: Option<bool>,
+    /// 输出目录
+    #[arg(long, visible_alias = "output", value_name = "目录")]
+    pub output_dir: Option<PathBuf>,
+    /// 时间表名称使用不带扩展名的源文件名
+    #[arg(long)]
+    pub strip_extension_in_name: Option<bool>,
+    /// 转换结束后在输出目录写入汇总的 index.csv
+    #[arg(long)]
+    pub index_csv: Option<bool>,
+    /// 移除逐帧数据完全相同的重复层
+    #[arg(long)]
+    pub dedupe_layers: Option<bool>,
+    /// 不输出警告，只保留进度和错误信息
+    #[arg(long)]
+    pub quiet_warnings: Option<bool>,
+    /// 先写入临时文件再重命名
+    #[arg(long)]
+    pub atomic: Option<bool>,
+    /// 文件夹中待转换文件数上限
+    #[arg(long, value_name = "N")]
+    pub max_files: Option<usize>,
+    /// 文件数超过上限时不再确认
+    #[arg(long)]
+    pub yes: Option<bool>,
+    /// 输出的 cel 编号上限
+    #[arg(long, value_name = "N")]
+    pub max_cell: Option<u32>,
+    /// cel 编号超过上限时的处理方式
+    #[arg(long, value_name = "error|clamp")]
+    pub on_over_cell: Option<OverCellPolicy>,
+    /// 按倍率缩放关键帧位置和时长（如 2/1、1/2、3）
+    #[arg(long, value_name = "倍率")]
+    pub retime: Option<Retime>,
+    /// 层名称写成固定字节宽度的字段（1-255）
+    #[arg(long, value_name = "N")]
+    pub name_field_width: Option<u8>,
+    /// TDTS 按 cut 拆分，每个 cut 输出一个 XDTS 文件
+    #[arg(long)]
+    pub split_cuts: Option<bool>,
+    /// 只编码并列出将要写入的文件，不写入
+    #[arg(long)]
+    pub dry_run: Option<bool>,
+    /// 输出每个时间表的详细信息（串行转换）
+    #[arg(long)]
+    pub verbose: Option<bool>,
+    /// 读取输入的 STS 文件并列出其中的层名称
+    #[arg(long)]
+    pub names: Option<bool>,
+    /// 关键帧数超过帧数的这个比例（0-1）时警告该层过密
+    #[arg(long, value_name = "比例")]
+    pub dense_layer_threshold: Option<f64>,
+    /// 层的数据跨度与时间表时长不一致时的处理方式
+    #[arg(long, value_name = "pad|clip|max")]
+    pub per_layer_duration: Option<LayerDuration>,
+    /// 输出该单元格从源数据到展开结果的推导过程
+    #[arg(long, value_name = "层:帧")]
+    pub explain: Option<ExplainTarget>,
+    /// 等价 cel 文件：每行一组编号，第一个为规范编号
+    #[arg(long, value_name = "文件")]
+    pub cel_equivalence: Option<String>,
+    /// cut 对照 CSV：每行 "原始 cut,名称"
+    #[arg(long, value_name = "文件")]
+    pub cut_map: Option<String>,
+    /// 只解析名称匹配此正则的时间表
+    #[arg(long, value_name = "正则")]
+    pub only_tables: Option<String>,
+    /// 转换结束后把生成的文件打包到此 zip
+    #[arg(long, value_name = "文件")]
+    pub zip_output: Option<PathBuf>,
+    /// 文本导出中保持的写法
+    #[arg(long, value_name = "repeat|blank|bar")]
+    pub hold_style: Option<HoldStyle>,
+    /// 写入每个 STS 后立即读回比对
+    #[arg(long)]
+    pub verify_after_write: Option<bool>,
+    /// 保留记号帧及每个关键帧的原始值，写入 JSON 导出
+    #[arg(long)]
+    pub preserve_symbols: Option<bool>,
+    /// 任何会丢失源数据的情况都视为错误
+    #[arg(long)]
+    pub strict: Option<bool>,
+    /// 列出输入文件中各时间表的 field 和 header 结构，不做转换
+    #[arg(long)]
+    pub list_fields: Option<bool>,
+    /// --list-fields 以 JSON 输出
+    #[arg(long)]
+    pub json: Option<bool>,
+    /// 文本导出中非零 cel 写为十六进制
+    #[arg(long)]
+    pub cell_as_hex: Option<bool>,
+    /// 按层名称前缀拆分时间表，每组输出一个文件
+    #[arg(long)]
+    pub split_by_prefix: Option<bool>,
+    /// --split-by-prefix 的分隔符（默认 _）
+    #[arg(long, value_name = "分隔符")]
+    pub prefix_delimiter: Option<String>,
+    /// STS 末尾追加 CRC32 尾部
+    #[arg(long)]
+    pub footer_crc: Option<bool>,
+    /// 从标准输入读取一个源文件
+    #[arg(long)]
+    pub from_stdin: Option<bool>,
+    /// --from-stdin 的源格式；不指定时按内容判断
+    #[arg(long, value_name = "xdts|tdts")]
+    pub input_format: Option<SourceFormat>,
+    /// 把转换结果写到标准输出
+    #[arg(long)]
+    pub stdout: Option<bool>,
+    /// 批处理时只打印失败和最后的总结
+    #[arg(long)]
+    pub quiet: Option<bool>,
+    /// 忽略 --state-file 的跳过，全部重新转换
+    #[arg(long)]
+    pub force: Option<bool>,
+    /// 转换文件夹时包括所有子文件夹
+    #[arg(long)]
+    pub recursive: Option<bool>,
+    /// 对目录中的每个源文件做 STS 编码/解码自检，不写入文件
+    #[arg(long, value_name = "目录")]
+    pub round_trip_check: Option<PathBuf>,
+    /// 比较两个源文件中各时间表的曝光变化，不做转换
+    #[arg(long, num_args = 2, value_names = ["旧", "新"])]
+    pub source_diff: Option<Vec<PathBuf>>,
+    /// 断点续转的状态文件
+    #[arg(long, value_name = "文件")]
+    pub state_file: Option<PathBuf>,
+    /// 没有名称的层的名称模板，{index} 替换为 track_no
+    #[arg(long, value_name = "模板")]
+    pub empty_layer_name: Option<String>,
+    /// 输出文件写入缓冲区的字节数
+    #[arg(long, value_name = "字节")]
+    pub buffer_size: Option<usize>,
+    /// 并行转换使用的线程数
+    #[arg(long, visible_alias = "jobs", value_name = "N")]
+    pub threads: Option<usize>,
+    /// --format long-csv 时省略空白行
+    #[arg(long)]
+    pub skip_blank_rows: Option<bool>,
+    /// 导出 XDTS 时写入的首行注释
+    #[arg(long, value_name = "注释")]
+    pub xdts_comment: Option<String>,
+    /// 只输出与该 STS 文件相比有变化或新增的层
+    #[arg(long, value_name = "文件")]
+    pub diff_against: Option<PathBuf>,
+    /// 每写入一个文件后暂停的毫秒数
+    #[arg(long, value_name = "毫秒")]
+    pub write_delay: Option<u64>,
+    /// 按 fieldName 选择 cel 所在的 field
+    #[arg(long, value_name = "名称")]
+    pub field_name: Option<String>,
+}
+
+impl Cli {
+    /// 把出现过的选项写入 `opts`，返回输入路径
+    pub fn apply(self, opts: &mut Options) -> Result<Vec<String>> {
+        set(&mut opts.trim_trailing_blank, self.trim_trailing_blank);
+        set(&mut opts.trim_margin, self.trim_margin);
+        set(&mut opts.container, self.container);
+        set(&mut opts.overwrite_if_newer, self.overwrite_if_newer);
+        set(&mut opts.confirm, self.confirm);
+        set(&mut opts.fractional, self.fractional);
+        set(&mut opts.normalize_cells, self.normalize_cells.map(Some));
+        set(&mut opts.preview_png, self.preview_png.map(Some));
+        set(&mut opts.max_file_size, self.max_file_size.map(Some));
+        if let Some(markers) = self.hold_markers {
+            opts.hold_markers = Some(
+                markers
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|m| !m.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            );
+        }
+        opts.also_ext.extend(
+            self.also_ext
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_string()),
+        );
+        set(&mut opts.reverse_layers, self.reverse_layers);
+        if let Some(fps) = self.fps {
+            if !(fps > 0.0 && fps.is_finite()) {
+                anyhow::bail!("无效的 --fps 值: {}", fps);
+            }
+            opts.fps = Some(fps);
+        }
+        set(&mut opts.normalize_names, self.normalize_names);
+        set(&mut opts.dump_bytes, self.dump_bytes.map(Some));
+        set(&mut opts.strict_names, self.strict_names);
+        if let Some(seconds) = self.timeout {
+            if !(seconds > 0.0 && seconds.is_finite()) {
+                anyhow::bail!("无效的 --timeout 值: {}", seconds);
+            }
+            opts.timeout = Some(Duration::from_secs_f64(seconds));
+        }
+        set(&mut opts.format, self.format);
+        set(&mut opts.null_cell_value, self.null_cell_value);
+        if let Some(base) = self.cel_base {
+            if base > 1 {
+                anyhow::bail!("无效的 --cel-base 值: {} (可选 0|1)", base);
+            }
+            opts.cel_base = base;
+        }
+        set(&mut opts.cell_offset, self.cell_offset);
+        set(&mut opts.underflow, self.underflow);
+        set(&mut opts.validate_only, self.validate_only);
+        set(&mut opts.subdir_per_source, self.subdir_per_source);
+        set(&mut opts.cell_width, self.cell_width);
+        set(&mut opts.json_schema, self.json_schema);
+        set(&mut opts.info, self.info);
+        if let Some(path) = self.layer_order_from {
+            opts.layer_order = Some(read_name_list(&path, "层顺序")?);
+        }
+        if let Some(path) = self.keep_layers {
+            opts.keep_layers = Some(read_name_list(&path, "层名单")?);
+        }
+        set(&mut opts.embed_provenance, self.embed_provenance);
+        set(&mut opts.cut_duration_tolerance, self.cut_duration_tolerance);
+        set(&mut opts.align_cut_durations, self.align_cut_durations);
+        set(&mut opts.metadata_only, self.metadata_only);
+        set(&mut opts.output_dir, self.output_dir.map(Some));
+        set(&mut opts.strip_extension_in_name, self.strip_extension_in_name);
+        set(&mut opts.index_csv, self.index_csv);
+        set(&mut opts.dedupe_layers, self.dedupe_layers);
+        set(&mut opts.quiet_warnings, self.quiet_warnings);
+        set(&mut opts.atomic, self.atomic);
+        set(&mut opts.max_files, self.max_files.map(Some));
+        set(&mut opts.yes, self.yes);
+        set(&mut opts.max_cell, self.max_cell.map(Some));
+        set(&mut opts.on_over_cell, self.on_over_cell);
+        set(&mut opts.retime, self.retime.map(Some));
+        if let Some(width) = self.name_field_width {
+            if width == 0 {
+                anyhow::bail!("无效的 --name-field-width 值: {} (可选 1-255)", width);
+            }
+            opts.name_field_width = Some(width);
+        }
+        set(&mut opts.split_cuts, self.split_cuts);
+        set(&mut opts.dry_run, self.dry_run);
+        set(&mut opts.verbose, self.verbose);
+        set(&mut opts.names, self.names);
+        if let Some(threshold) = self.dense_layer_threshold {
+            if !(threshold > 0.0 && threshold <= 1.0) {
+                anyhow::bail!("无效的 --dense-layer-threshold 值: {} (应在 0 到 1 之间)", threshold);
+            }
+            opts.dense_layer_threshold = Some(threshold);
+        }
+        set(&mut opts.per_layer_duration, self.per_layer_duration.map(Some));
+        set(&mut opts.explain, self.explain.map(Some));
+        if let Some(path) = self.cel_equivalence {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("无法读取 cel 等价文件: {}", path))?;
+            opts.cel_equivalence = Some(parse_cel_equivalence(&content)?);
+        }
+        if let Some(path) = self.cut_map {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("无法读取 cut 对照文件: {}", path))?;
+            opts.cut_map = Some(parse_cut_map(&content)?);
+        }
+        if let Some(pattern) = self.only_tables {
+            opts.only_tables = Some(
+                Regex::new(&pattern)
+                    .with_context(|| format!("无效的 --only-tables 正则表达式: {}", pattern))?,
+            );
+        }
+        set(&mut opts.zip_output, self.zip_output.map(Some));
+        set(&mut opts.hold_style, self.hold_style.map(Some));
+        set(&mut opts.verify_after_write, self.verify_after_write);
+        set(&mut opts.preserve_symbols, self.preserve_symbols);
+        set(&mut opts.strict, self.strict);
+        set(&mut opts.list_fields, self.list_fields);
+        set(&mut opts.json, self.json);
+        set(&mut opts.cell_as_hex, self.cell_as_hex);
+        set(&mut opts.split_by_prefix, self.split_by_prefix);
+        if let Some(delimiter) = self.prefix_delimiter {
+            if delimiter.is_empty() {
+                anyhow::bail!("--prefix-delimiter 的分隔符不能为空");
+            }
+            opts.prefix_delimiter = Some(delimiter);
+        }
+        set(&mut opts.footer_crc, self.footer_crc);
+        set(&mut opts.from_stdin, self.from_stdin);
+        set(&mut opts.input_format, self.input_format.map(Some));
+        set(&mut opts.stdout, self.stdout);
+        set(&mut opts.quiet, self.quiet);
+        set(&mut opts.force, self.force);
+        set(&mut opts.recursive, self.recursive);
+        set(&mut opts.round_trip_check, self.round_trip_check.map(Some));
+        if let Some([old, new]) = self.source_diff.and_then(|paths| <[PathBuf; 2]>::try_from(paths).ok()) {
+            opts.source_diff = Some((old, new));
+        }
+        set(&mut opts.state_file, self.state_file.map(Some));
+        if let Some(template) = self.empty_layer_name {
+            if template.is_empty() {
+                anyhow::bail!("--empty-layer-name 的名称模板不能为空");
+            }
+            opts.empty_layer_name = Some(template);
+        }
+        if let Some(size) = self.buffer_size {
+            if size == 0 {
+                anyhow::bail!("无效的 --buffer-size 值: {} (至少为 1)", size);
+            }
+            opts.buffer_size = Some(size);
+        }
+        if let Some(threads) = self.threads {
+            if threads == 0 {
+                anyhow::bail!("无效的 --threads 值: {} (至少为 1)", threads);
+            }
+            opts.threads = Some(threads);
+        }
+        set(&mut opts.skip_blank_rows, self.skip_blank_rows);
+        set(&mut opts.xdts_comment, self.xdts_comment.map(Some));
+        set(&mut opts.diff_against, self.diff_against.map(Some));
+        if let Some(millis) = self.write_delay {
+            opts.write_delay = Duration::from_millis(millis);
+        }
+        set(&mut opts.field_name, self.field_name.map(Some));
+
+        Ok(self.inputs)
+    }
+}
+
+/// 开关选项（`Option<bool>` 字段）单写 `--strict` 即为 true，也可写成 `--strict=false`
+fn switch_arg(arg: Arg) -> Arg {
+    let values = arg.get_possible_values();
+    if !values.iter().map(PossibleValue::get_name).eq(["true", "false"]) {
+        return arg;
+    }
+    arg.num_args(0..=1)
+        .require_equals(true)
+        .default_missing_value("true")
+        .value_name("true|false")
+        .hide_possible_values(true)
+}
+
+/// 选项出现过时覆盖原值
+fn set<T>(slot: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *slot = value;
+    }
+}
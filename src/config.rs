@@ -0,0 +1,123 @@
+//! 按目录覆盖转换选项（`.xdts2sts.toml`）
+//!
+//! 源文件所在目录中有 `.xdts2sts.toml` 时，其中的选项覆盖命令行选项；
+//! 转换文件夹时，拖放的文件夹及中间各级子文件夹中的配置也依次生效，越内层越优先。
+//! 键名与命令行选项相同（去掉 `--`）：
+//!
+//! ```toml
+//! field-name = "CELL"
+//! cell-offset = -1
+//! strict = false         # 开关选项：false 关闭命令行上打开的开关
+//! also-ext = ["xdt"]     # 数组：对每个元素重复该选项
+//! ```
+
+use crate::options::Options;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+pub const CONFIG_FILE_NAME: &str = ".xdts2sts.toml";
+
+/// 从扫描的根目录（拖放的文件夹）到源文件所在目录，由外到内依次读取各级的 `.xdts2sts.toml`
+/// 覆盖选项，子文件夹因此继承根目录的配置；没有 `root` 时只读源文件所在目录。
+/// 都没有配置文件时返回 `None`
+pub fn dir_options(input_path: &Path, root: Option<&Path>, opts: &Options) -> Result<Option<Options>> {
+    let Some(dir) = input_path.parent() else {
+        return Ok(None);
+    };
+    let root = root.filter(|root| dir.starts_with(root)).unwrap_or(dir);
+    let mut dirs: Vec<&Path> = dir.ancestors().take_while(|d| *d != root).collect();
+    dirs.push(root);
+
+    let mut overridden: Option<Options> = None;
+    for dir in dirs.into_iter().rev() {
+        let path = dir.join(CONFIG_FILE_NAME);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("无法读取配置文件: {}", path.display())),
+        };
+
+        let table: toml::Table = content
+            .parse()
+            .with_context(|| format!("解析配置文件失败: {}", path.display()))?;
+        let base = overridden.as_ref().unwrap_or(opts);
+        let dir_opts = table_to_args(&table)
+            .and_then(|args| base.with_overrides(&args))
+            .with_context(|| format!("配置文件中的选项无效: {}", path.display()))?;
+        overridden = Some(dir_opts);
+    }
+    Ok(overridden)
+}
+
+/// 把 TOML 表转换为等价的命令行参数
+fn table_to_args(table: &toml::Table) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let flag = format!("--{}", key);
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(b) => args.push(format!("{}={}", flag, b)),
+                toml::Value::String(s) => args.extend([flag.clone(), s.clone()]),
+                toml::Value::Integer(n) => args.extend([flag.clone(), n.to_string()]),
+                toml::Value::Float(n) => args.extend([flag.clone(), n.to_string()]),
+                _ => anyhow::bail!("'{}' 的值类型不支持 (应为字符串、数字、布尔值或数组)", key),
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// 在临时目录下建立 `root/sub`，按需写入两级配置文件
+    fn scan_tree(name: &str, root_toml: &str, sub_toml: Option<&str>) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("xdts2sts-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join(CONFIG_FILE_NAME), root_toml).unwrap();
+        if let Some(sub_toml) = sub_toml {
+            std::fs::write(root.join("sub").join(CONFIG_FILE_NAME), sub_toml).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn false_switch_turns_off_command_line_flag() {
+        let root = scan_tree("config-false", "strict = false\ncell-offset = -1\n", None);
+        let (opts, _) = Options::parse_args(&["--strict".to_string()]).unwrap();
+        assert!(opts.strict);
+
+        let dir_opts = dir_options(&root.join("a.xdts"), Some(&root), &opts).unwrap().unwrap();
+        assert!(!dir_opts.strict);
+        assert_eq!(dir_opts.cell_offset, -1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn subfolder_inherits_root_config() {
+        let root = scan_tree(
+            "config-walk",
+            "field-name = \"A\"\ncell-offset = -1\n",
+            Some("field-name = \"B\"\n"),
+        );
+        let opts = Options::default();
+        let input = root.join("sub").join("a.xdts");
+
+        let dir_opts = dir_options(&input, Some(&root), &opts).unwrap().unwrap();
+        assert_eq!(dir_opts.field_name.as_deref(), Some("B"));
+        assert_eq!(dir_opts.cell_offset, -1);
+
+        // 单独拖放的文件只读所在目录
+        let dir_opts = dir_options(&input, None, &opts).unwrap().unwrap();
+        assert_eq!(dir_opts.field_name.as_deref(), Some("B"));
+        assert_eq!(dir_opts.cell_offset, 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! STSX 多时间表容器
+//!
+//! 布局 (所有整数均为 little-endian):
+//!
+//! ```text
+//! [4 bytes]  魔数 "STSX"
+//! [1 byte ]  容器版本 (当前为 1)
+//! [2 bytes]  条目数 N
+//! N × 索引项:
+//!   [4 bytes]  STS 数据偏移 (相对文件开头)
+//!   [4 bytes]  STS 数据长度
+//!   [2 bytes]  名称长度 L
+//!   [L bytes]  名称 (UTF-8)
+//! N × STS 数据 (与单独输出的 .sts 文件完全相同)
+//! ```
+
+use crate::converter::write_output;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"STSX";
+const VERSION: u8 = 1;
+
+/// 容器中的一个条目
+#[derive(Debug, Clone)]
+pub struct ContainerEntry {
+    /// 条目名称 (即单独输出时的 .sts 文件名)
+    pub name: String,
+    /// STS 数据
+    pub data: Vec<u8>,
+}
+
+/// 将多个 STS 数据打包为 .stsx 容器
+pub fn encode_container(entries: &[ContainerEntry]) -> Result<Vec<u8>> {
+    if entries.len() > u16::MAX as usize {
+        anyhow::bail!("条目过多: {}, 最大支持 {} 个", entries.len(), u16::MAX);
+    }
+
+    // 计算索引区大小以确定第一个数据的偏移
+    let mut index_size = 0usize;
+    for entry in entries {
+        if entry.name.len() > u16::MAX as usize {
+            anyhow::bail!("条目名称过长: '{}'", entry.name);
+        }
+        index_size += 4 + 4 + 2 + entry.name.len();
+    }
+
+    let payload_size: usize = entries.iter().map(|e| e.data.len()).sum();
+    let mut data = Vec::with_capacity(4 + 1 + 2 + index_size + payload_size);
+
+    // === 容器头 ===
+    data.extend_from_slice(MAGIC);
+    data.push(VERSION);
+    data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+
+    // === 索引区 ===
+    let mut offset = data.len() + index_size;
+    for entry in entries {
+        let offset_u32 = u32::try_from(offset).context("容器过大，偏移超出 4GB")?;
+        data.extend_from_slice(&offset_u32.to_le_bytes());
+        data.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        data.extend_from_slice(entry.name.as_bytes());
+        offset += entry.data.len();
+    }
+
+    // === 数据区 ===
+    for entry in entries {
+        data.extend_from_slice(&entry.data);
+    }
+
+    Ok(data)
+}
+
+/// 写入 .stsx 容器文件，`atomic` 时经临时文件重命名写入
+pub fn save_container(
+    entries: &[ContainerEntry],
+    output_path: &Path,
+    atomic: bool,
+    buffer_size: usize,
+) -> Result<()> {
+    let data = encode_container(entries)?;
+    write_output(output_path, &data, atomic, buffer_size)
+}
+
+/// 解析 .stsx 容器数据
+pub fn decode_container(data: &[u8]) -> Result<Vec<ContainerEntry>> {
+    if data.len() < 7 || &data[..4] != MAGIC {
+        anyhow::bail!("不是有效的 STSX 容器");
+    }
+    if data[4] != VERSION {
+        anyhow::bail!("不支持的 STSX 容器版本: {}", data[4]);
+    }
+
+    let count = u16::from_le_bytes([data[5], data[6]]) as usize;
+    let mut pos = 7;
+    let mut entries = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let header = data.get(pos..pos + 10).context("STSX 索引区被截断")?;
+        let offset = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        let name_len = u16::from_le_bytes(header[8..10].try_into().unwrap()) as usize;
+        pos += 10;
+
+        let name_bytes = data.get(pos..pos + name_len).context("STSX 索引区被截断")?;
+        let name = String::from_utf8(name_bytes.to_vec()).context("STSX 条目名称不是有效的 UTF-8")?;
+        pos += name_len;
+
+        let payload = data
+            .get(offset..offset + length)
+            .with_context(|| format!("STSX 条目 '{}' 的数据超出文件范围", name))?;
+
+        entries.push(ContainerEntry {
+            name,
+            data: payload.to_vec(),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// 读取 .stsx 容器文件
+pub fn load_container(path: &Path) -> Result<Vec<ContainerEntry>> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("无法打开文件: {}", path.display()))?;
+    decode_container(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<ContainerEntry> {
+        vec![
+            ContainerEntry {
+                name: "cut_001_A.sts".to_string(),
+                data: vec![0x11, 1, 2, 3],
+            },
+            ContainerEntry {
+                name: "cut_002_镜头.sts".to_string(),
+                data: vec![0x11; 9],
+            },
+        ]
+    }
+
+    #[test]
+    fn decode_returns_encoded_entries() {
+        let data = encode_container(&entries()).unwrap();
+        let decoded = decode_container(&data).unwrap();
+        assert_eq!(decoded.len(), 2);
+        for (decoded, entry) in decoded.iter().zip(entries()) {
+            assert_eq!(decoded.name, entry.name);
+            assert_eq!(decoded.data, entry.data);
+        }
+    }
+
+    #[test]
+    fn truncated_container_is_rejected() {
+        let data = encode_container(&entries()).unwrap();
+        // 截在索引区中间（第一个索引项的名称里）
+        let err = decode_container(&data[..7 + 12]).unwrap_err();
+        assert!(err.to_string().contains("索引区被截断"), "{}", err);
+        // 截掉最后一个条目的部分数据
+        let err = decode_container(&data[..data.len() - 1]).unwrap_err();
+        assert!(err.to_string().contains("超出文件范围"), "{}", err);
+    }
+}
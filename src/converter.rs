@@ -1,32 +1,494 @@
+use crate::options::{
+    CellWidth, ExplainTarget, LayerDuration, NormalizeCells, Options, OverCellPolicy, Retime,
+    UnderflowPolicy,
+};
+use crate::parser::describe_cell_value;
+use crate::sts::StsFile;
 use crate::types::*;
+use crate::warning::{Warning, Warnings};
 use anyhow::{Context, Result};
 use encoding_rs::SHIFT_JIS;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
 
-pub fn save_sts(timesheet: &Timesheet, output_path: &Path, verbose: bool) -> Result<()> {
+/// 转换结果统计
+#[derive(Debug, Clone, Default)]
+pub struct ConvertReport {
+    /// 最终写入的帧数
+    pub frame_count: usize,
+    /// 被裁剪掉的末尾空白帧数
+    pub trimmed_frames: usize,
+    /// cel 编号压缩的映射（仅包含实际发生变化的映射）
+    pub cell_mappings: Vec<CellMapping>,
+    /// 按 `--cel-equivalence` 实际发生的替换，每层一组
+    pub cel_substitutions: Vec<CellMapping>,
+    /// 各层有画面的帧范围，顺序与输出的层一致
+    pub layer_ranges: Vec<LayerRange>,
+    /// 按 `--per-layer-duration` 处理的、数据跨度与时间表时长不一致的层
+    pub span_adjustments: Vec<SpanAdjustment>,
+}
+
+/// 数据跨度与时间表时长不一致的一层
+#[derive(Debug, Clone)]
+pub struct SpanAdjustment {
+    pub layer: String,
+    /// 该层自身的数据跨度（帧数）
+    pub span: usize,
+    /// 源数据中时间表的时长
+    pub duration: usize,
+    /// 处理后时间表的帧数
+    pub frame_count: usize,
+    pub policy: LayerDuration,
+}
+
+impl SpanAdjustment {
+    /// 格式化为 "数据 12 帧 / 时长 24 帧 → 补齐到 24 帧"
+    pub fn describe(&self) -> String {
+        let action = if self.span > self.frame_count {
+            format!("超出部分裁到 {} 帧", self.frame_count)
+        } else {
+            match self.policy {
+                LayerDuration::Pad | LayerDuration::Max if self.span < self.frame_count => {
+                    format!("补齐到 {} 帧", self.frame_count)
+                }
+                LayerDuration::Clip if self.span < self.frame_count => {
+                    format!("第 {} 帧之后留空", self.span)
+                }
+                // 该层决定了扩展后的时间表长度
+                _ => format!("时间表扩展到 {} 帧", self.frame_count),
+            }
+        };
+        format!("数据 {} 帧 / 时长 {} 帧 → {}", self.span, self.duration, action)
+    }
+}
+
+/// 层自身的数据跨度：以空白关键帧结束时为该帧之前，否则包含最后一个关键帧
+fn layer_span(layer: &Layer) -> usize {
+    match layer.frames.last() {
+        Some(last) if last.cell == 0 => last.frame as usize,
+        Some(last) => last.frame as usize + 1,
+        None => 0,
+    }
+}
+
+/// 格式化为 "A 0–10, B 空"
+pub fn describe_ranges(ranges: &[LayerRange]) -> String {
+    ranges
+        .iter()
+        .map(|r| match r.range {
+            Some((first, last)) => format!("{} {}–{}", r.layer, first, last),
+            None => format!("{} 空", r.layer),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 格式化为 "A 5–7, 20–21"，只包含有间隙的层
+pub fn describe_gaps(ranges: &[LayerRange]) -> String {
+    ranges
+        .iter()
+        .filter(|r| !r.gaps.is_empty())
+        .map(|r| format!("{} {}", r.layer, format_spans(&r.gaps)))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// 一层中第一个和最后一个非空帧，以及其间的空白间隙
+#[derive(Debug, Clone)]
+pub struct LayerRange {
+    pub layer: String,
+    /// (第一个非空帧, 最后一个非空帧)，帧号从 0 开始；整层为空时为 None
+    pub range: Option<(usize, usize)>,
+    /// 前后都有画面的空白区间 (起始帧, 结束帧)
+    pub gaps: Vec<(usize, usize)>,
+}
+
+/// 格式化为 "5–7, 20–21"
+pub fn format_spans(spans: &[(usize, usize)]) -> String {
+    spans
+        .iter()
+        .map(|(start, end)| format!("{}–{}", start, end))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 查找前后都有画面的连续空白帧区间
+fn find_gaps(cells: &[u32]) -> Vec<(usize, usize)> {
+    let mut gaps = Vec::new();
+    let mut gap_start = None;
+    let mut seen_content = false;
+
+    for (frame, &cell) in cells.iter().enumerate() {
+        if cell == 0 {
+            if seen_content && gap_start.is_none() {
+                gap_start = Some(frame);
+            }
+        } else {
+            if let Some(start) = gap_start.take() {
+                gaps.push((start, frame - 1));
+            }
+            seen_content = true;
+        }
+    }
+
+    gaps
+}
+
+/// 一组 cel 编号映射
+#[derive(Debug, Clone)]
+pub struct CellMapping {
+    /// 所属层名称，全局映射时为 None
+    pub layer: Option<String>,
+    /// 原编号 → 新编号
+    pub map: BTreeMap<u32, u32>,
+}
+
+impl CellMapping {
+    /// 格式化为 "1→1, 3→2, 7→3"
+    pub fn describe(&self) -> String {
+        self.map
+            .iter()
+            .map(|(from, to)| format!("{}→{}", from, to))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// 将时间表写出为 STS 文件：整个文件先在内存中编码，再经 [`write_output`] 的缓冲区成批写入，
+/// 不会按 cell 逐个写入；`verbose` 时报告的实际大小在写入完成后读取
+pub fn save_sts(
+    timesheet: &Timesheet,
+    output_path: &Path,
+    verbose: bool,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<ConvertReport> {
+    let (data, report) = encode_sts(timesheet, verbose, opts, warnings)?;
+
+    // 写入 STS 文件
+    write_output(output_path, &data, opts.atomic, opts.buffer_size())?;
+
+    if opts.verify_after_write {
+        crate::sts::verify_written(output_path, &data)?;
+    }
+
+    if verbose {
+        let actual_size = std::fs::metadata(output_path)?.len();
+        println!("\n文件已生成: {}", output_path.display());
+        println!("  实际大小: {} 字节", actual_size);
+    }
+
+    Ok(report)
+}
+
+/// 写入输出文件。`atomic` 时先写入同目录的临时文件，刷新到磁盘后再重命名到目标位置，
+/// 中途失败会删除临时文件，其他程序不会看到写了一半的文件。
+/// 数据经 `buffer_size` 字节的缓冲区分块写入
+pub fn write_output(output_path: &Path, data: &[u8], atomic: bool, buffer_size: usize) -> Result<()> {
+    if !atomic {
+        let file = File::create(output_path)
+            .with_context(|| format!("无法创建文件: {}", output_path.display()))?;
+        return write_buffered(file, data, buffer_size)
+            .map(drop)
+            .with_context(|| format!("写入文件失败: {}", output_path.display()));
+    }
+
+    let file_name = output_path
+        .file_name()
+        .context("无效的输出路径")?
+        .to_string_lossy();
+    let temp_path =
+        output_path.with_file_name(format!(".{}.tmp-{}", file_name, std::process::id()));
+
+    let result = File::create(&temp_path)
+        .with_context(|| format!("无法创建临时文件: {}", temp_path.display()))
+        .and_then(|file| {
+            write_buffered(file, data, buffer_size)
+                .and_then(|file| file.sync_all())
+                .with_context(|| format!("写入文件失败: {}", temp_path.display()))
+        })
+        .and_then(|_| {
+            std::fs::rename(&temp_path, output_path)
+                .with_context(|| format!("无法重命名为: {}", output_path.display()))
+        });
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// 按缓冲区大小分块写入并刷新，返回底层文件
+fn write_buffered(file: File, data: &[u8], buffer_size: usize) -> std::io::Result<File> {
+    let mut writer = BufWriter::with_capacity(buffer_size, file);
+    // 每块正好填满缓冲区，写入按缓冲区大小成批发出
+    for chunk in data.chunks(buffer_size) {
+        writer.write_all(chunk)?;
+    }
+    writer.into_inner().map_err(|e| e.into_error())
+}
+
+/// 将时间表编码为 STS 二进制数据
+pub fn encode_sts(
+    timesheet: &Timesheet,
+    verbose: bool,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<(Vec<u8>, ConvertReport)> {
+    let (all_layers_cells, report) = expand_timesheet(timesheet, verbose, opts, warnings)?;
+    let warnings_before = warnings.len();
+    let data = write_sts_bytes(timesheet, &all_layers_cells, report.frame_count, opts, warnings)?;
+    if opts.strict {
+        ensure_lossless(warnings, warnings_before)?;
+    }
+
+    if let Some(limit) = opts.dump_bytes {
+        println!("\n{} 的前 {} 字节:", timesheet.name, limit.min(data.len()));
+        print!(
+            "{}",
+            hex_dump(
+                &data,
+                all_layers_cells.len(),
+                report.frame_count,
+                opts.cell_width,
+                opts.name_field_width,
+                limit
+            )
+        );
+    }
+
+    Ok((data, report))
+}
+
+/// 在内存中编码为 STS 再解析回来，确认层名称和逐帧数据与展开结果一致（`--round-trip-check`）
+pub fn check_round_trip(timesheet: &Timesheet, opts: &Options) -> Result<()> {
+    let mut warnings = Warnings::default();
+    let (all_layers_cells, report) = expand_timesheet(timesheet, false, opts, &mut warnings)?;
+    let data = write_sts_bytes(timesheet, &all_layers_cells, report.frame_count, opts, &mut warnings)?;
+    let sts = crate::sts::parse_sts(&data).context("编码结果无法解析")?;
+
+    if sts.layers.len() != timesheet.layers.len() {
+        anyhow::bail!(
+            "展开 {} 层，读回 {} 层",
+            timesheet.layers.len(),
+            sts.layers.len()
+        );
+    }
+
+    for (idx, ((layer, cells), actual)) in timesheet
+        .layers
+        .iter()
+        .zip(&all_layers_cells)
+        .zip(&sts.layers)
+        .enumerate()
+    {
+        if layer.name != actual.name {
+            anyhow::bail!("第{}层名称为 '{}'，读回 '{}'", idx + 1, layer.name, actual.name);
+        }
+        if actual.cells.len() != cells.len() {
+            anyhow::bail!(
+                "第{}层 '{}' 展开 {} 帧，读回 {} 帧",
+                idx + 1,
+                layer.name,
+                cells.len(),
+                actual.cells.len()
+            );
+        }
+        if let Some(frame) = (0..cells.len()).find(|&f| cells[f] != actual.cells[f]) {
+            anyhow::bail!(
+                "第{}层 '{}' 帧 {} 展开为 {}，读回 {}",
+                idx + 1,
+                layer.name,
+                frame + 1,
+                cells[frame],
+                actual.cells[frame]
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// STS 文件头的字节数
+pub const HEADER_SIZE: usize = 23;
+
+/// cell 宽度标志字节（偏移 21）中表示带 CRC32 尾部的位（`--footer-crc`）
+pub const FOOTER_CRC_FLAG: u8 = 0x80;
+
+/// CRC32 尾部的字节数
+pub const FOOTER_CRC_SIZE: usize = 4;
+
+/// 编码后 STS 数据各区域的字节数
+#[derive(Debug, Clone, Copy)]
+pub struct StsSizes {
+    pub header: usize,
+    pub frame_data: usize,
+    pub names: usize,
+    /// CRC32 尾部（`--footer-crc`），没有时为 0
+    pub footer: usize,
+}
+
+impl StsSizes {
+    /// 按编码结果划分文件头、帧数据区和层名称区
+    pub fn of(data: &[u8], layer_count: usize, frame_count: usize, cell_width: CellWidth) -> Self {
+        let frame_data = layer_count * frame_count * cell_width.bytes();
+        let footer = if has_footer_crc(data) { FOOTER_CRC_SIZE } else { 0 };
+        StsSizes {
+            header: HEADER_SIZE,
+            frame_data,
+            names: data.len().saturating_sub(HEADER_SIZE + frame_data + footer),
+            footer,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.header + self.frame_data + self.names + self.footer
+    }
+}
+
+/// 文件头的 cell 宽度标志中是否带有 CRC32 尾部标志位
+pub fn has_footer_crc(data: &[u8]) -> bool {
+    data.get(21).is_some_and(|&flag| flag & FOOTER_CRC_FLAG != 0)
+}
+
+/// 生成带注释的十六进制转储，标出文件头各字段和数据区
+pub fn hex_dump(
+    data: &[u8],
+    layer_count: usize,
+    frame_count: usize,
+    cell_width: CellWidth,
+    name_field_width: Option<u8>,
+    limit: usize,
+) -> String {
+    let mut regions: Vec<(usize, usize, String)> = vec![
+        (0, 1, "标识符 (0x11)".to_string()),
+        (1, 17, "签名 \"ShiraheiTimeSheet\"".to_string()),
+        (18, 1, format!("层数 = {}", layer_count)),
+        (19, 2, format!("帧数 = {}", frame_count)),
+        (21, 1, if has_footer_crc(data) {
+            format!("cell 宽度标志 = {} | 0x80 (CRC32 尾部)", cell_width.header_flag())
+        } else {
+            format!("cell 宽度标志 = {}", cell_width.header_flag())
+        }),
+        (22, 1, match name_field_width {
+            Some(width) => format!("名称字段宽度 = {}", width),
+            None => "名称字段宽度 = 0 (长度前缀)".to_string(),
+        }),
+    ];
+
+    let sizes = StsSizes::of(data, layer_count, frame_count, cell_width);
+    let layer_size = frame_count * cell_width.bytes();
+    let mut offset = sizes.header;
+    for layer_idx in 0..layer_count {
+        regions.push((offset, layer_size, format!("帧数据: 第{}层", layer_idx + 1)));
+        offset += layer_size;
+    }
+    regions.push((offset, sizes.names, "层名称区".to_string()));
+    if sizes.footer > 0 {
+        regions.push((offset + sizes.names, sizes.footer, "CRC32 尾部".to_string()));
+    }
+
+    let limit = limit.min(data.len());
+    let mut out = String::new();
+
+    for (start, len, label) in regions {
+        let end = (start + len).min(limit);
+        let mut row_start = start;
+        let mut first_row = true;
+
+        while row_start < end {
+            let row_end = (row_start + 16).min(end);
+            let hex: Vec<String> = data[row_start..row_end]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            let line = format!(
+                "  {:08x}  {:<48}  {}",
+                row_start,
+                hex.join(" "),
+                if first_row { label.as_str() } else { "" }
+            );
+            out.push_str(line.trim_end());
+            out.push('\n');
+            first_row = false;
+            row_start = row_end;
+        }
+    }
+
+    out
+}
+
+/// 展开时间表的所有层，得到最终写入 STS 的逐帧 cell 数据
+pub fn expand_timesheet(
+    timesheet: &Timesheet,
+    verbose: bool,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<(Vec<Vec<u32>>, ConvertReport)> {
+    let mut report = ConvertReport::default();
     let layer_count = timesheet.layers.len();
-    let frame_count = timesheet.frame_count as usize;
+    let mut frame_count = timesheet.frame_count as usize;
+    let warnings_before = warnings.len();
 
     if verbose {
         println!("\n正在转换: {}", timesheet.name);
         println!("  层数: {}", layer_count);
         println!("  帧数: {}", frame_count);
+        if let Some(seconds) = opts.duration_seconds(frame_count) {
+            println!("  时长: {:.2} 秒", seconds);
+        }
     }
 
     if layer_count > 255 {
         anyhow::bail!("层数过多: {}, 最大支持 255 层", layer_count);
     }
 
-    if frame_count > 65535 {
-        anyhow::bail!("帧数过多: {}, 最大支持 65535 帧", frame_count);
+    // 层的数据跨度与时长不一致时，按 `--per-layer-duration` 处理
+    let duration = frame_count;
+    let spans: Vec<usize> = timesheet.layers.iter().map(layer_span).collect();
+    if opts.per_layer_duration == Some(LayerDuration::Max) {
+        frame_count = spans.iter().copied().fold(frame_count, usize::max);
+    }
+
+    // 展开前检查帧数，避免按超长的时长分配内存；裁剪末尾空白帧时只需展开到最后的画面加边距为止
+    let full_frame_count = frame_count;
+    if opts.trim_trailing_blank {
+        let content_end = timesheet
+            .layers
+            .iter()
+            .map(|layer| content_end(layer, frame_count, opts))
+            .max()
+            .unwrap_or(0);
+        frame_count = frame_count.min(content_end.saturating_add(opts.trim_margin as usize).max(1));
     }
+    check_frame_count(frame_count)?;
 
     // 展开所有层的帧数据
-    let mut all_layers_cells: Vec<Vec<u16>> = Vec::new();
+    let mut all_layers_cells: Vec<Vec<u32>> = Vec::new();
+    let warnings_before_expand = warnings.len();
     for (idx, layer) in timesheet.layers.iter().enumerate() {
-        let cells = expand_frames(&layer.frames, frame_count);
+        let mut cells = expand_frames(layer, frame_count, opts, warnings);
+
+        if let Some(policy) = opts.per_layer_duration {
+            let span = spans[idx];
+            if span > 0 && span != duration {
+                if policy == LayerDuration::Clip && span < frame_count {
+                    cells[span..].fill(0);
+                }
+                let adjustment = SpanAdjustment {
+                    layer: layer.name.clone(),
+                    span,
+                    duration,
+                    frame_count: full_frame_count,
+                    policy,
+                };
+                report.span_adjustments.push(adjustment);
+            }
+        }
         all_layers_cells.push(cells);
 
         if verbose {
@@ -34,79 +496,897 @@ pub fn save_sts(timesheet: &Timesheet, output_path: &Path, verbose: bool) -> Res
                 all_layers_cells[idx].iter().collect();
             let keyframe_count = layer.frames.len();
             println!(
-                "  第{}层 '{}': {}个关键帧, {}个唯一cell值",
+                "  第{}层 '{}' (track {}): {}个关键帧, {}个唯一cell值",
                 idx + 1,
                 layer.name,
+                layer.track_no,
                 keyframe_count,
                 unique_cells.len()
             );
         }
     }
 
-    // 写入 STS 文件
-    let mut file = File::create(output_path)
-        .with_context(|| format!("无法创建文件: {}", output_path.display()))?;
+    // `--cell-offset` 使 cel 小于 1：默认视为错误，clamp-to-null 时保留空白和警告
+    if opts.underflow == UnderflowPolicy::Error {
+        let mut underflows: Vec<String> = warnings
+            .iter()
+            .skip(warnings_before_expand)
+            .filter_map(|warning| match warning {
+                Warning::CelUnderflow { layer, cell, .. } => {
+                    Some(format!("层 '{}' 的 cel {}", layer, cell))
+                }
+                _ => None,
+            })
+            .collect();
+        underflows.dedup();
+        if !underflows.is_empty() {
+            anyhow::bail!(
+                "cel 按 --cell-offset {} 偏移后小于 1 (可用 --underflow clamp-to-null 写为空白): {}",
+                opts.cell_offset,
+                underflows.join(", ")
+            );
+        }
+    }
+
+    if verbose {
+        for adjustment in &report.span_adjustments {
+            println!("  层时长 (层 '{}'): {}", adjustment.layer, adjustment.describe());
+        }
+    }
+
+    if verbose && !opts.quiet_warnings {
+        let mut span_warnings = Warnings::default();
+        check_frame_spans(timesheet, &mut span_warnings);
+        for warning in span_warnings.iter() {
+            println!("  ⚠ {}", warning);
+        }
+    }
+
+    // 等价的 cel 统一为规范编号（在压缩编号之前）
+    if let Some(equivalence) = &opts.cel_equivalence {
+        report.cel_substitutions =
+            substitute_equivalent_cels(&mut all_layers_cells, &timesheet.layers, equivalence);
+
+        if verbose {
+            for mapping in &report.cel_substitutions {
+                if let Some(layer) = &mapping.layer {
+                    println!("  cel 等价替换 (层 '{}'): {}", layer, mapping.describe());
+                }
+            }
+        }
+    }
+
+    // 压缩 cel 编号
+    if let Some(scope) = opts.normalize_cells {
+        report.cell_mappings = normalize_cells(&mut all_layers_cells, &timesheet.layers, scope);
+
+        if verbose {
+            for mapping in &report.cell_mappings {
+                match &mapping.layer {
+                    Some(layer) => println!("  cel 重新编号 (层 '{}'): {}", layer, mapping.describe()),
+                    None => println!("  cel 重新编号: {}", mapping.describe()),
+                }
+            }
+        }
+    }
+
+    // 裁剪末尾空白帧
+    if opts.trim_trailing_blank {
+        let trimmed_count = trimmed_frame_count(&all_layers_cells, frame_count, opts.trim_margin);
+        report.trimmed_frames = full_frame_count - trimmed_count;
+        frame_count = trimmed_count;
+        for cells in &mut all_layers_cells {
+            cells.truncate(frame_count);
+        }
+
+        if verbose && report.trimmed_frames > 0 {
+            println!(
+                "  裁剪末尾空白帧: {} 帧 (剩余 {} 帧)",
+                report.trimmed_frames, frame_count
+            );
+        }
+    }
+
+    if let Some(threshold) = opts.dense_layer_threshold {
+        let before = warnings.len();
+        check_dense_layers(timesheet, threshold, warnings);
+        if verbose && !opts.quiet_warnings {
+            for warning in warnings.iter().skip(before) {
+                println!("  ⚠ {}", warning);
+            }
+        }
+    }
+
+    if let Some(max_cell) = opts.max_cell {
+        check_max_cell(timesheet, &mut all_layers_cells, max_cell, opts.on_over_cell, warnings)?;
+    }
+
+    if opts.strict {
+        // 超出时长被裁掉的关键帧平时只在 --verbose/--validate-only 时报告
+        if opts.per_layer_duration != Some(LayerDuration::Max) {
+            let mut span_warnings = Warnings::default();
+            check_frame_spans(timesheet, &mut span_warnings);
+            for warning in span_warnings.iter().filter(|w| w.is_data_loss()) {
+                warnings.push(warning.clone());
+            }
+        }
+        ensure_lossless(warnings, warnings_before)?;
+    }
+
+    report.layer_ranges = timesheet
+        .layers
+        .iter()
+        .zip(&all_layers_cells)
+        .map(|(layer, cells)| LayerRange {
+            layer: layer.name.clone(),
+            range: cells
+                .iter()
+                .position(|&c| c != 0)
+                .zip(cells.iter().rposition(|&c| c != 0)),
+            gaps: find_gaps(cells),
+        })
+        .collect();
+
+    if verbose {
+        println!("  画面范围: {}", describe_ranges(&report.layer_ranges));
+        let gaps = describe_gaps(&report.layer_ranges);
+        if !gaps.is_empty() {
+            println!("  空白间隙: {}", gaps);
+        }
+    }
+
+    report.frame_count = frame_count;
+    Ok((all_layers_cells, report))
+}
+
+/// 按 STS 格式序列化已展开的 cell 数据
+fn write_sts_bytes(
+    timesheet: &Timesheet,
+    all_layers_cells: &[Vec<u32>],
+    frame_count: usize,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Vec<u8>> {
+    let layer_count = all_layers_cells.len();
+    let cell_width = opts.cell_width;
+    let mut data = Vec::with_capacity(HEADER_SIZE + layer_count * frame_count * cell_width.bytes());
 
     // === 文件头 (23 bytes) ===
 
     // STS 标识符
-    file.write_all(&[0x11])?;
+    data.push(0x11);
 
     // 固定字符串 "ShiraheiTimeSheet"
-    file.write_all(b"ShiraheiTimeSheet")?;
+    data.extend_from_slice(b"ShiraheiTimeSheet");
 
     // 层数 (1 byte)
-    file.write_all(&[layer_count as u8])?;
+    data.push(layer_count as u8);
 
     // 帧数 (2 bytes, little-endian)
-    file.write_all(&(frame_count as u16).to_le_bytes())?;
+    data.extend_from_slice(&(frame_count as u16).to_le_bytes());
 
-    // 填充 (2 bytes)
-    file.write_all(&[0x00, 0x00])?;
+    // cell 宽度标志 (1 byte, 标准 u16 为 0；带 CRC32 尾部时最高位置 1) + 名称字段宽度 (1 byte, 长度前缀为 0)
+    let flag = if opts.footer_crc {
+        cell_width.header_flag() | FOOTER_CRC_FLAG
+    } else {
+        cell_width.header_flag()
+    };
+    data.extend_from_slice(&[flag, opts.name_field_width.unwrap_or(0)]);
 
-    // === 帧数据区 (layer_count × frame_count × 2 bytes) ===
-    for (_layer_idx, cells) in all_layers_cells.iter().enumerate() {
-        for (_frame_idx, &cell) in cells.iter().enumerate() {
-            file.write_all(&cell.to_le_bytes())?;
+    // === 帧数据区 (layer_count × frame_count × cell 宽度) ===
+    for (layer, cells) in timesheet.layers.iter().zip(all_layers_cells) {
+        for &cell in cells {
+            match cell_width {
+                CellWidth::U8 => {
+                    let cell = u8::try_from(cell).map_err(|_| {
+                        anyhow::anyhow!(
+                            "层 '{}' 的 cel 值 {} 超出 1 字节 cell 的范围 (最大 255)",
+                            layer.name,
+                            cell
+                        )
+                    })?;
+                    data.push(cell);
+                }
+                CellWidth::U16 => {
+                    let cell = u16::try_from(cell).map_err(|_| {
+                        anyhow::anyhow!(
+                            "层 '{}' 的 cel 值 {} 超出 2 字节 cell 的范围 (最大 65535)，可用 --cell-width 4",
+                            layer.name,
+                            cell
+                        )
+                    })?;
+                    data.extend_from_slice(&cell.to_le_bytes());
+                }
+                CellWidth::U32 => data.extend_from_slice(&cell.to_le_bytes()),
+            }
         }
     }
 
     // === 层名称区 ===
 
     for layer in &timesheet.layers {
-        let name = &layer.name;
+        // 分解形式 (NFD) 的名称先合成为 NFC，避免本可编码的字符被误判
+        let name: Cow<str> = if opts.normalize_names {
+            Cow::Owned(layer.name.nfc().collect())
+        } else {
+            Cow::Borrowed(&layer.name)
+        };
 
         // 编码为 Shift-JIS
-        let (name_bytes, _, had_errors) = SHIFT_JIS.encode(name);
+        let (name_bytes, _, had_errors) = SHIFT_JIS.encode(&name);
 
         if had_errors {
-            eprintln!("  警告: 层名称 '{}' 包含无法编码为Shift-JIS的字符", name);
+            if opts.strict_names {
+                let bad_chars: Vec<String> = unencodable_chars(&name)
+                    .into_iter()
+                    .map(|c| format!("'{}' (U+{:04X})", c, c as u32))
+                    .collect();
+                anyhow::bail!(
+                    "层名称 '{}' 包含无法编码为Shift-JIS的字符: {}",
+                    name,
+                    bad_chars.join(", ")
+                );
+            }
+            warnings.push(Warning::UnencodableName { name: name.to_string() });
         }
 
-        let name_bytes = if name_bytes.len() > 255 {
-            eprintln!("  警告: 层名称过长，截断为255字节: '{}'", name);
-            &name_bytes[..255]
+        let max_len = opts.name_field_width.map_or(255, usize::from);
+        let name_bytes = if name_bytes.len() > max_len {
+            warnings.push(Warning::NameTruncated {
+                name: name.to_string(),
+                limit: max_len,
+            });
+            truncate_sjis(&name_bytes, max_len)
         } else {
             &name_bytes
         };
 
-        // 写入: [1字节长度][N字节名称]
-        file.write_all(&[name_bytes.len() as u8])?;
-        file.write_all(name_bytes)?;
+        match opts.name_field_width {
+            // 写入: [N字节名称，不足部分用空格填充]
+            Some(width) => {
+                data.extend_from_slice(name_bytes);
+                data.resize(data.len() + usize::from(width) - name_bytes.len(), b' ');
+            }
+            // 写入: [1字节长度][N字节名称]
+            None => {
+                data.push(name_bytes.len() as u8);
+                data.extend_from_slice(name_bytes);
+            }
+        }
     }
 
-    if verbose {
-        let actual_size = file.metadata()?.len();
-        println!("\n文件已生成: {}", output_path.display());
-        println!("  实际大小: {} 字节", actual_size);
+    // === CRC32 尾部 (4 bytes, little-endian)：覆盖前面的全部字节 ===
+    if opts.footer_crc {
+        let crc = crc32fast::hash(&data);
+        data.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    Ok(data)
+}
+
+/// 把 Shift-JIS 字节串截断到不超过 `max_len` 字节，不切断双字节字符
+fn truncate_sjis(bytes: &[u8], max_len: usize) -> &[u8] {
+    // 从头逐字符前进：0x81–0x9F、0xE0–0xFC 为双字节字符的首字节，其余为单字节
+    let mut end = 0;
+    while end < bytes.len() {
+        let char_len = match bytes[end] {
+            0x81..=0x9F | 0xE0..=0xFC => 2,
+            _ => 1,
+        };
+        if end + char_len > max_len {
+            break;
+        }
+        end += char_len;
     }
+    &bytes[..end.min(bytes.len())]
+}
+
+/// 找出名称中无法编码为 Shift-JIS 的字符（去重，保持出现顺序）
+fn unencodable_chars(name: &str) -> Vec<char> {
+    let mut chars = Vec::new();
+    let mut buf = [0u8; 4];
+    for c in name.chars() {
+        let (_, _, had_errors) = SHIFT_JIS.encode(c.encode_utf8(&mut buf));
+        if had_errors && !chars.contains(&c) {
+            chars.push(c);
+        }
+    }
+    chars
+}
+
+/// 层中最后一个非空白画面之后的帧号：最后的关键帧不是空白时一直保持到 `frame_count`
+fn content_end(layer: &Layer, frame_count: usize, opts: &Options) -> usize {
+    let last = layer
+        .frames
+        .iter()
+        .rev()
+        .find(|frame| (frame.frame as usize) < frame_count);
+    match last {
+        Some(last) if last.explicit_null && opts.null_cell_value == 0 => last.frame as usize,
+        Some(last) if !last.explicit_null && last.cell == 0 => last.frame as usize,
+        Some(_) => frame_count,
+        None => 0,
+    }
+}
+
+fn check_frame_count(frame_count: usize) -> Result<()> {
+    if frame_count > 65535 {
+        anyhow::bail!("帧数过多: {}, 最大支持 65535 帧", frame_count);
+    }
+    Ok(())
+}
+
+/// 按等价表把各层的 cel 替换为规范编号，返回每层实际发生的替换
+fn substitute_equivalent_cels(
+    all_layers_cells: &mut [Vec<u32>],
+    layers: &[Layer],
+    equivalence: &BTreeMap<u32, u32>,
+) -> Vec<CellMapping> {
+    let mut mappings = Vec::new();
+    for (cells, layer) in all_layers_cells.iter_mut().zip(layers) {
+        let mut map = BTreeMap::new();
+        for cell in cells.iter_mut() {
+            if let Some(&canonical) = equivalence.get(cell) {
+                map.insert(*cell, canonical);
+                *cell = canonical;
+            }
+        }
+        if !map.is_empty() {
+            mappings.push(CellMapping {
+                layer: Some(layer.name.clone()),
+                map,
+            });
+        }
+    }
+    mappings
+}
+
+/// 把非零 cel 值按大小重新映射为 1, 2, 3...，0 (空白) 保持不变
+fn normalize_cells(
+    all_layers_cells: &mut [Vec<u32>],
+    layers: &[Layer],
+    scope: NormalizeCells,
+) -> Vec<CellMapping> {
+    fn build_map<'a>(cells: impl Iterator<Item = &'a u32>) -> BTreeMap<u32, u32> {
+        let mut map: BTreeMap<u32, u32> = cells.filter(|&&c| c != 0).map(|&c| (c, 0)).collect();
+        for (idx, value) in map.values_mut().enumerate() {
+            *value = idx as u32 + 1;
+        }
+        map
+    }
+
+    fn is_identity(map: &BTreeMap<u32, u32>) -> bool {
+        map.iter().all(|(from, to)| from == to)
+    }
+
+    let mut mappings = Vec::new();
+
+    match scope {
+        NormalizeCells::PerLayer => {
+            for (cells, layer) in all_layers_cells.iter_mut().zip(layers) {
+                let map = build_map(cells.iter());
+                for cell in cells.iter_mut() {
+                    *cell = map.get(cell).copied().unwrap_or(0);
+                }
+                if !is_identity(&map) {
+                    mappings.push(CellMapping {
+                        layer: Some(layer.name.clone()),
+                        map,
+                    });
+                }
+            }
+        }
+        NormalizeCells::Global => {
+            let map = build_map(all_layers_cells.iter().flatten());
+            for cell in all_layers_cells.iter_mut().flatten() {
+                *cell = map.get(cell).copied().unwrap_or(0);
+            }
+            if !is_identity(&map) {
+                mappings.push(CellMapping { layer: None, map });
+            }
+        }
+    }
+
+    mappings
+}
+
+/// 计算裁剪末尾空白帧后的帧数（至少保留 1 帧）
+fn trimmed_frame_count(all_layers_cells: &[Vec<u32>], frame_count: usize, margin: u32) -> usize {
+    let last_used = all_layers_cells
+        .iter()
+        .filter_map(|cells| cells.iter().rposition(|&cell| cell != 0))
+        .max();
+
+    let keep = match last_used {
+        Some(last) => last + 1 + margin as usize,
+        None => margin as usize,
+    };
+
+    if frame_count == 0 {
+        0
+    } else {
+        keep.clamp(1, frame_count)
+    }
+}
+
+/// 检查最终写入的 cel 编号是否超过 `--max-cell`：报错，或截断为上限并按层警告
+fn check_max_cell(
+    timesheet: &Timesheet,
+    all_layers_cells: &mut [Vec<u32>],
+    max_cell: u32,
+    policy: OverCellPolicy,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    for (layer, cells) in timesheet.layers.iter().zip(all_layers_cells.iter_mut()) {
+        let Some(frame) = cells.iter().position(|&c| c > max_cell) else {
+            continue;
+        };
+
+        if policy == OverCellPolicy::Error {
+            anyhow::bail!(
+                "层 '{}' 第 {} 帧的 cel {} 超过上限 {}",
+                layer.name,
+                frame + 1,
+                cells[frame],
+                max_cell
+            );
+        }
 
+        warnings.push(Warning::CellClamped {
+            table: timesheet.name.clone(),
+            layer: layer.name.clone(),
+            frame,
+            cell: cells[frame],
+            max_cell,
+        });
+        for cell in cells.iter_mut().filter(|c| **c > max_cell) {
+            *cell = max_cell;
+        }
+    }
     Ok(())
 }
 
-/// 将关键帧列表展开为完整的帧序列
-fn expand_frames(frames: &[Frame], frame_count: usize) -> Vec<u16> {
-    let mut cells = vec![0u16; frame_count];
+/// 按倍率缩放所有关键帧的帧号和时长，保持随之拉伸或压缩。
+/// 缩放后落在同一帧的关键帧只保留源数据中靠后的一个，并发出警告
+pub fn retime(timesheet: &mut Timesheet, factor: Retime, warnings: &mut Warnings) {
+    timesheet.frame_count = factor.scale(timesheet.frame_count);
+
+    for layer in &mut timesheet.layers {
+        let mut frames: Vec<Frame> = Vec::with_capacity(layer.frames.len());
+        for mut frame in layer.frames.drain(..) {
+            frame.frame = factor.scale(frame.frame);
+            if let Some(previous) = frames.last_mut() {
+                if previous.frame == frame.frame {
+                    warnings.push(Warning::RetimeCollision {
+                        table: timesheet.name.clone(),
+                        layer: layer.name.clone(),
+                        frame: frame.frame,
+                    });
+                    *previous = frame;
+                    continue;
+                }
+            }
+            frames.push(frame);
+        }
+        layer.frames = frames;
+    }
+}
+
+/// 按指定的名称顺序重排层：列表中没有的层按原顺序排在最后，列表中有但不存在的名称跳过
+pub fn reorder_layers(timesheet: &mut Timesheet, order: &[String], warnings: &mut Warnings) {
+    let mut remaining: Vec<Option<Layer>> = timesheet.layers.drain(..).map(Some).collect();
+    let mut missing = Vec::new();
+
+    for name in order {
+        let found = remaining
+            .iter_mut()
+            .find(|slot| slot.as_ref().is_some_and(|layer| &layer.name == name))
+            .and_then(Option::take);
+        match found {
+            Some(layer) => timesheet.layers.push(layer),
+            None => missing.push(name.clone()),
+        }
+    }
+
+    let unlisted: Vec<Layer> = remaining.into_iter().flatten().collect();
+    let unlisted_names: Vec<String> = unlisted.iter().map(|layer| layer.name.clone()).collect();
+    timesheet.layers.extend(unlisted);
+
+    if !missing.is_empty() || !unlisted_names.is_empty() {
+        warnings.push(Warning::LayerOrderMismatch {
+            table: timesheet.name.clone(),
+            missing,
+            unlisted: unlisted_names,
+        });
+    }
+}
+
+/// 只保留名单中的层，按名单顺序排列；名单中有但不存在的名称跳过并报告
+pub fn keep_layers(timesheet: &mut Timesheet, names: &[String], warnings: &mut Warnings) {
+    let mut remaining: Vec<Option<Layer>> = timesheet.layers.drain(..).map(Some).collect();
+    let mut missing = Vec::new();
+
+    for name in names {
+        let found = remaining
+            .iter_mut()
+            .find(|slot| slot.as_ref().is_some_and(|layer| &layer.name == name))
+            .and_then(Option::take);
+        match found {
+            Some(layer) => timesheet.layers.push(layer),
+            // 名单中重复列出的名称只取一次
+            None if timesheet.layers.iter().any(|layer| &layer.name == name) => {}
+            None => missing.push(name.clone()),
+        }
+    }
+
+    if !missing.is_empty() {
+        warnings.push(Warning::KeptLayersMissing {
+            table: timesheet.name.clone(),
+            missing,
+        });
+    }
+}
+
+/// `--split-by-prefix` 中没有前缀的层所在组的名称
+pub const DEFAULT_PREFIX_GROUP: &str = "default";
+
+/// 按层名称中第一个分隔符之前的前缀把时间表拆成多个，按前缀首次出现的顺序排列。
+/// 拆出的时间表名称为 `原名称_前缀`；没有分隔符（或前缀为空）的层归入 [`DEFAULT_PREFIX_GROUP`]
+pub fn split_by_prefix(timesheet: Timesheet, delimiter: &str) -> Vec<Timesheet> {
+    let mut groups: Vec<(String, Vec<Layer>)> = Vec::new();
+    for layer in timesheet.layers {
+        let prefix = match layer.name.split_once(delimiter) {
+            Some((prefix, _)) if !prefix.is_empty() => prefix.to_string(),
+            _ => DEFAULT_PREFIX_GROUP.to_string(),
+        };
+        match groups.iter_mut().find(|(name, _)| *name == prefix) {
+            Some((_, layers)) => layers.push(layer),
+            None => groups.push((prefix, vec![layer])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(prefix, layers)| Timesheet {
+            name: format!("{}_{}", timesheet.name, prefix),
+            frame_count: timesheet.frame_count,
+            layers,
+            version: timesheet.version,
+            cut: timesheet.cut.clone(),
+            comment: timesheet.comment.clone(),
+        })
+        .collect()
+}
+
+/// 移除展开后逐帧数据与前面某层完全相同的层，只保留第一个，并报告被移除的层名
+pub fn dedupe_layers(timesheet: &mut Timesheet, opts: &Options, warnings: &mut Warnings) {
+    let frame_count = timesheet.frame_count as usize;
+    let mut kept: Vec<(Layer, Vec<u32>)> = Vec::new();
+
+    for layer in timesheet.layers.drain(..) {
+        let cells = expand_frames(&layer, frame_count, opts, &mut Warnings::default());
+        match kept.iter().find(|(_, kept_cells)| *kept_cells == cells) {
+            Some((original, _)) => warnings.push(Warning::DuplicateLayer {
+                table: timesheet.name.clone(),
+                layer: layer.name,
+                same_as: original.name.clone(),
+            }),
+            None => kept.push((layer, cells)),
+        }
+    }
+
+    timesheet.layers = kept.into_iter().map(|(layer, _)| layer).collect();
+}
+
+/// 读取 STS 文件并还原为时间表（STS → XDTS 反向转换）：逐帧 cell 压缩为关键帧，
+/// 每层在帧 0 和 cel 变化的帧上各有一个关键帧。时间表名称为 `源文件名->源文件名（不含扩展名）`
+pub fn load_sts(path: &Path) -> Result<Timesheet> {
+    let sts = crate::sts::load_sts(path)?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let frame_count = sts.frame_count as u32;
+
+    let mut layers = Vec::with_capacity(sts.layers.len());
+    for (track_no, layer) in sts.layers.into_iter().enumerate() {
+        let mut frames: Vec<Frame> = Vec::new();
+        for (frame, &cell) in layer.cells.iter().enumerate() {
+            if frames.last().is_some_and(|last| last.cell == cell) {
+                continue;
+            }
+            frames.push(Frame {
+                frame: frame as u32,
+                cell,
+                explicit_null: cell == 0 && frame > 0,
+                data: Vec::new(),
+                source: None,
+            });
+        }
+        layers.push(Layer {
+            name: layer.name,
+            track_no,
+            frames,
+        });
+    }
+
+    Ok(Timesheet {
+        name: format!("{}->{}", file_name, stem),
+        frame_count,
+        layers,
+        version: None,
+        cut: None,
+        comment: None,
+    })
+}
+
+/// 把时间表转换为只有一个 timeTable 的 XDTS 根对象（cut 名称取时间表名称中的源文件部分）
+pub fn timesheet_to_xdts(timesheet: &Timesheet) -> XDTSRoot {
+    let cut = timesheet
+        .name
+        .split_once("->")
+        .map_or(timesheet.name.as_str(), |(source, _)| source);
+    let cut = Path::new(cut).file_stem().map_or(cut.into(), |stem| stem.to_string_lossy());
+    crate::xdts::timesheets_to_xdts(&cut, &[timesheet])
+}
+
+/// 与旧 STS 逐层比较：只保留旧文件中没有同名层、或展开后逐帧数据不同的层，
+/// 返回保留的层名
+pub fn diff_layers(timesheet: &mut Timesheet, old: &StsFile, opts: &Options) -> Result<Vec<String>> {
+    let (all_layers_cells, _) =
+        expand_timesheet(timesheet, false, opts, &mut Warnings::default())?;
+
+    let changed: Vec<bool> = all_layers_cells
+        .iter()
+        .zip(&timesheet.layers)
+        .map(|(cells, layer)| {
+            !old.layers.iter().any(|old_layer| {
+                old_layer.name == layer.name && old_layer.cells == *cells
+            })
+        })
+        .collect();
+    let mut flags = changed.into_iter();
+    timesheet.layers.retain(|_| flags.next().unwrap_or(true));
+
+    Ok(timesheet.layers.iter().map(|layer| layer.name.clone()).collect())
+}
+
+/// 逐层比较两个版本的时间表（按层名称对齐），返回每个有变化的层的描述：
+/// 变化的帧范围（从 1 开始）、新增或删除的层
+pub fn diff_timesheets(old: &Timesheet, new: &Timesheet, opts: &Options) -> Result<Vec<String>> {
+    let (old_cells, _) = expand_timesheet(old, false, opts, &mut Warnings::default())?;
+    let (new_cells, _) = expand_timesheet(new, false, opts, &mut Warnings::default())?;
+
+    let mut lines = Vec::new();
+    for (layer, cells) in new.layers.iter().zip(&new_cells) {
+        let old_layer = old.layers.iter().position(|old_layer| old_layer.name == layer.name);
+        let Some(old_idx) = old_layer else {
+            lines.push(format!("层 '{}': 新增", layer.name));
+            continue;
+        };
+
+        // 帧数不同时，较短一方缺少的帧视为空白
+        let before = &old_cells[old_idx];
+        let changed: Vec<usize> = (0..before.len().max(cells.len()))
+            .filter(|&f| before.get(f).copied().unwrap_or(0) != cells.get(f).copied().unwrap_or(0))
+            .collect();
+        if !changed.is_empty() {
+            lines.push(format!("层 '{}': 帧 {}", layer.name, format_frame_ranges(&changed)));
+        }
+    }
+    for layer in &old.layers {
+        if !new.layers.iter().any(|new_layer| new_layer.name == layer.name) {
+            lines.push(format!("层 '{}': 已删除", layer.name));
+        }
+    }
+
+    Ok(lines)
+}
+
+/// 把升序的帧号（从 0 开始）合并为 "3–5, 12" 形式（从 1 开始）
+fn format_frame_ranges(frames: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &frame in frames {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == frame => *end = frame,
+            _ => ranges.push((frame, frame)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                (start + 1).to_string()
+            } else {
+                format!("{}–{}", start + 1, end + 1)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 追踪一个单元格的推导过程：源数据值、解析结果、所属关键帧和展开后的值；
+/// 时间表中没有该层时返回 None
+pub fn explain_cell(
+    timesheet: &Timesheet,
+    target: &ExplainTarget,
+    opts: &Options,
+) -> Result<Option<Vec<String>>> {
+    let Some(index) = timesheet.layers.iter().position(|layer| layer.name == target.layer) else {
+        return Ok(None);
+    };
+    let layer = &timesheet.layers[index];
+    let frame = target.frame - 1;
+
+    let mut lines = vec![format!(
+        "{}: 层 '{}' (track {}) 第 {} 帧",
+        timesheet.name, layer.name, layer.track_no, target.frame
+    )];
+
+    // 优化后的关键帧中，最后一个不晚于该帧的关键帧决定该帧的值
+    let parsed = match layer.frames.iter().rev().find(|keyframe| keyframe.frame <= frame) {
+        Some(keyframe) => {
+            lines.push(format!("  所属关键帧: 第 {} 帧", keyframe.frame + 1));
+            match &keyframe.source {
+                Some(value) => lines.push(format!(
+                    "  源数据值: \"{}\" ({})",
+                    value,
+                    describe_cell_value(value, opts)
+                )),
+                None => lines.push("  源数据值: 无 (首个关键帧之前补的空白)".to_string()),
+            }
+            let null = if keyframe.explicit_null { " (显式空白)" } else { "" };
+            lines.push(format!("  解析结果: cel {}{}", keyframe.cell, null));
+            keyframe.cell
+        }
+        None => {
+            lines.push("  所属关键帧: 无，视为空白".to_string());
+            0
+        }
+    };
+
+    let (all_layers_cells, report) =
+        expand_timesheet(timesheet, false, opts, &mut Warnings::default())?;
+    match all_layers_cells[index].get(frame as usize) {
+        Some(&cell) if cell != parsed => lines.push(format!(
+            "  展开结果: cel {} (经 --cel-base、--cel-equivalence、--normalize-cells 等处理)",
+            cell
+        )),
+        Some(&cell) => lines.push(format!("  展开结果: cel {}", cell)),
+        None => lines.push(format!("  展开结果: 超出输出的 {} 帧", report.frame_count)),
+    }
+
+    Ok(Some(lines))
+}
+
+/// `--strict`：`warnings` 中从 `from` 开始有会丢失数据的警告时返回错误，列出丢失的内容
+pub fn ensure_lossless(warnings: &Warnings, from: usize) -> Result<()> {
+    let losses: Vec<String> = warnings
+        .iter()
+        .skip(from)
+        .filter(|warning| warning.is_data_loss())
+        .map(|warning| format!("  - {}", warning))
+        .collect();
+    if !losses.is_empty() {
+        anyhow::bail!("--strict: 转换会丢失以下数据:\n{}", losses.join("\n"));
+    }
+    Ok(())
+}
+
+/// 检查各层展开后中间的空白间隙（前后都有画面的连续空白帧）
+pub fn check_gaps(timesheet: &Timesheet, opts: &Options, warnings: &mut Warnings) {
+    let frame_count = timesheet.frame_count as usize;
+    for layer in &timesheet.layers {
+        let cells = expand_frames(layer, frame_count, opts, &mut Warnings::default());
+        let gaps = find_gaps(&cells);
+        if !gaps.is_empty() {
+            warnings.push(Warning::ExposureGaps {
+                table: timesheet.name.clone(),
+                layer: layer.name.clone(),
+                gaps,
+            });
+        }
+    }
+}
+
+/// 检查优化后关键帧数超过帧数 `threshold` 比例的层（关键帧几乎逐帧，多为导出问题）
+pub fn check_dense_layers(timesheet: &Timesheet, threshold: f64, warnings: &mut Warnings) {
+    let frame_count = timesheet.frame_count;
+    for layer in &timesheet.layers {
+        let keyframes = layer.frames.len();
+        if frame_count > 0 && keyframes as f64 > frame_count as f64 * threshold {
+            warnings.push(Warning::DenseLayer {
+                table: timesheet.name.clone(),
+                layer: layer.name.clone(),
+                keyframes,
+                frame_count,
+            });
+        }
+    }
+}
+
+/// 内容结束帧早于时长的这个比例时视为时长不匹配
+const SHORT_SPAN_RATIO: f64 = 0.5;
+
+/// 检查各层关键帧与时间表时长是否匹配：
+/// 关键帧超出时长的部分会被裁掉；内容远早于时长结束则可能是时长填错
+pub fn check_frame_spans(timesheet: &Timesheet, warnings: &mut Warnings) {
+    let frame_count = timesheet.frame_count;
+
+    for layer in &timesheet.layers {
+        let clipped: Vec<u32> = layer
+            .frames
+            .iter()
+            .map(|f| f.frame)
+            .filter(|&frame| frame >= frame_count)
+            .collect();
+        if !clipped.is_empty() {
+            warnings.push(Warning::FramesClipped {
+                table: timesheet.name.clone(),
+                layer: layer.name.clone(),
+                frames: clipped,
+                frame_count,
+            });
+            continue;
+        }
+
+        // 最后一个关键帧是空白，且之前有内容：内容在该帧结束
+        let has_content = layer.frames.iter().any(|f| f.cell != 0);
+        if let Some(last) = layer.frames.last() {
+            if has_content
+                && last.cell == 0
+                && (last.frame as f64) < frame_count as f64 * SHORT_SPAN_RATIO
+            {
+                warnings.push(Warning::SpanTooShort {
+                    table: timesheet.name.clone(),
+                    layer: layer.name.clone(),
+                    end_frame: last.frame,
+                    frame_count,
+                });
+            }
+        }
+    }
+}
+
+/// 把源数据中从 0 开始的 cel 编号按 `--cel-base` 和 `--cell-offset` 偏移；空白 (0) 不变，
+/// 小于 1 时写为空白并记录下溢，超出范围时保留最大值并警告
+fn shift_cel(cell: u32, layer: &str, opts: &Options, warnings: &mut Warnings) -> u32 {
+    if cell == 0 {
+        return 0;
+    }
+
+    let shifted = i64::from(cell) + i64::from(opts.cel_base) + i64::from(opts.cell_offset);
+    if shifted < 1 {
+        warnings.push(Warning::CelUnderflow {
+            layer: layer.to_string(),
+            cell,
+            offset: opts.cell_offset,
+        });
+        return 0;
+    }
+
+    u32::try_from(shifted).unwrap_or_else(|_| {
+        warnings.push(Warning::CelOutOfRange {
+            layer: layer.to_string(),
+            cell,
+        });
+        u32::MAX
+    })
+}
+
+/// 将层的关键帧列表展开为完整的帧序列，显式空白写为 `--null-cell-value`，
+/// 非空 cel 按 `--cel-base` 和 `--cell-offset` 偏移
+fn expand_frames(
+    layer: &Layer,
+    frame_count: usize,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Vec<u32> {
+    let frames = &layer.frames;
+    let mut cells = vec![0u32; frame_count];
+    debug_assert!(
+        frames.windows(2).all(|pair| pair[0].frame < pair[1].frame),
+        "层 '{}' 的关键帧未按帧号严格升序排列",
+        layer.name
+    );
 
     if frames.is_empty() {
         return cells;
@@ -114,7 +1394,11 @@ fn expand_frames(frames: &[Frame], frame_count: usize) -> Vec<u16> {
 
     for i in 0..frames.len() {
         let start_frame = frames[i].frame as usize;
-        let cell_value = frames[i].cell;
+        let cell_value = if frames[i].explicit_null {
+            opts.null_cell_value
+        } else {
+            shift_cel(frames[i].cell, &layer.name, opts, warnings)
+        };
 
         // 确定结束帧
         let end_frame = if i + 1 < frames.len() {
@@ -124,10 +1408,117 @@ fn expand_frames(frames: &[Frame], frame_count: usize) -> Vec<u16> {
         };
 
         // 填充该区间的所有帧
-        for frame_no in start_frame..end_frame.min(frame_count) {
-            cells[frame_no] = cell_value;
+        let end_frame = end_frame.min(frame_count);
+        if start_frame < end_frame {
+            cells[start_frame..end_frame].fill(cell_value);
         }
     }
 
     cells
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlong_duration_is_rejected_before_expanding() {
+        let frame = |frame: u32, cell: u32| Frame {
+            frame,
+            cell,
+            explicit_null: false,
+            data: Vec::new(),
+            source: None,
+        };
+        let mut ts = Timesheet {
+            name: "t->T".to_string(),
+            frame_count: u32::MAX,
+            layers: vec![Layer {
+                name: "A".to_string(),
+                track_no: 0,
+                frames: vec![frame(0, 1), frame(10, 0)],
+            }],
+            version: None,
+            cut: None,
+            comment: None,
+        };
+
+        let err = expand_timesheet(&ts, false, &Options::default(), &mut Warnings::default())
+            .unwrap_err();
+        assert!(err.to_string().contains("帧数过多"), "{}", err);
+
+        // 裁剪末尾空白帧后只剩 10 帧，不必展开整个时长
+        let opts = Options {
+            trim_trailing_blank: true,
+            ..Options::default()
+        };
+        let (cells, report) = expand_timesheet(&ts, false, &opts, &mut Warnings::default()).unwrap();
+        assert_eq!(report.frame_count, 10);
+        assert_eq!(report.trimmed_frames, u32::MAX as usize - 10);
+        assert_eq!(cells[0], [1; 10]);
+
+        // 最后的关键帧不是空白时一直保持到时长结束，裁剪也无法缩短
+        ts.layers[0].frames.pop();
+        let err = expand_timesheet(&ts, false, &opts, &mut Warnings::default()).unwrap_err();
+        assert!(err.to_string().contains("帧数过多"), "{}", err);
+    }
+
+    #[test]
+    fn large_cel_needs_u32_cell_width() {
+        let frame = |frame: u32, cell: u32| Frame {
+            frame,
+            cell,
+            explicit_null: false,
+            data: Vec::new(),
+            source: None,
+        };
+        let ts = Timesheet {
+            name: "t->T".to_string(),
+            frame_count: 3,
+            layers: vec![Layer {
+                name: "A".to_string(),
+                track_no: 0,
+                frames: vec![frame(0, 1), frame(2, 70000)],
+            }],
+            version: None,
+            cut: None,
+            comment: None,
+        };
+        let opts = Options {
+            cell_width: CellWidth::U32,
+            ..Options::default()
+        };
+
+        let (data, _) = encode_sts(&ts, false, &opts, &mut Warnings::default()).unwrap();
+        let cells: Vec<u32> = data[23..35]
+            .chunks(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(cells, [1, 1, 70000]);
+
+        // 标准 2 字节 cell 放不下
+        let err = encode_sts(&ts, false, &Options::default(), &mut Warnings::default()).unwrap_err();
+        assert!(err.to_string().contains("超出 2 字节 cell 的范围"));
+    }
+
+    #[test]
+    fn truncate_sjis_does_not_split_kanji() {
+        // "漢" 为 0x8A 0xBF；前面加一个半角字符使截断边界落在双字节字符中间
+        for prefix in ["", "A"] {
+            let name = format!("{}{}", prefix, "漢".repeat(200));
+            let (bytes, _, had_errors) = SHIFT_JIS.encode(&name);
+            assert!(!had_errors);
+            assert!(bytes.len() > 255);
+
+            let truncated = truncate_sjis(&bytes, 255);
+            assert!(truncated.len() <= 255);
+            assert_eq!(truncated.len(), 254 + prefix.len());
+            let last = *truncated.last().unwrap();
+            assert!(!matches!(last, 0x81..=0x9F | 0xE0..=0xFC), "末尾为首字节 {:#X}", last);
+
+            let (decoded, _, had_errors) = SHIFT_JIS.decode(truncated);
+            assert!(!had_errors);
+            assert_eq!(decoded, format!("{}{}", prefix, "漢".repeat(127)));
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! 批处理输出的汇总索引（`--index-csv`）
+
+use crate::otcsv::csv_field;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// 索引中的一行：一个输出的时间表
+#[derive(Debug, Clone)]
+pub struct IndexRow {
+    /// 输出文件
+    pub output: PathBuf,
+    /// 源文件
+    pub source: PathBuf,
+    /// 时间表名称
+    pub timesheet: String,
+    pub layer_count: usize,
+    pub frame_count: usize,
+    /// 输出数据大小（字节）
+    pub size: u64,
+}
+
+/// 写入 index.csv
+pub fn save_index_csv(rows: &[IndexRow], output_path: &Path) -> Result<()> {
+    let mut text = String::from("output,source,timesheet,layers,frames,bytes\n");
+    for row in rows {
+        text.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&row.output.display().to_string()),
+            csv_field(&row.source.display().to_string()),
+            csv_field(&row.timesheet),
+            row.layer_count,
+            row.frame_count,
+            row.size
+        ));
+    }
+
+    std::fs::write(output_path, text)
+        .with_context(|| format!("写入文件失败: {}", output_path.display()))
+}
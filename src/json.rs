@@ -0,0 +1,173 @@
+//! 时间表的 JSON 导出及其 JSON Schema
+
+use crate::options::{CellWidth, OutputFormat};
+use crate::parser::TableFields;
+use crate::provenance::Provenance;
+use crate::types::SourceFormat;
+use crate::types::Timesheet;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+/// 带来源信息的时间表，`_meta` 放在最前面
+#[derive(Serialize)]
+struct WithProvenance<'a> {
+    #[serde(rename = "_meta")]
+    meta: &'a Provenance,
+    #[serde(flatten)]
+    timesheet: &'a Timesheet,
+}
+
+/// 将解析后的时间表（关键帧形式）写为 JSON
+pub fn save_json(
+    timesheet: &Timesheet,
+    output_path: &Path,
+    verbose: bool,
+    provenance: Option<&Provenance>,
+    cell_as_hex: bool,
+) -> Result<()> {
+    let text = encode_json(timesheet, provenance, cell_as_hex)?;
+
+    std::fs::write(output_path, text)
+        .with_context(|| format!("写入文件失败: {}", output_path.display()))?;
+
+    if verbose {
+        println!("\n文件已生成: {}", output_path.display());
+    }
+
+    Ok(())
+}
+
+/// 将时间表编码为 JSON 文本；`cell_as_hex` 时关键帧中非零的 cel 写为 "0x1F" 形式的字符串
+pub fn encode_json(
+    timesheet: &Timesheet,
+    provenance: Option<&Provenance>,
+    cell_as_hex: bool,
+) -> Result<String> {
+    let mut value = match provenance {
+        Some(meta) => serde_json::to_value(WithProvenance { meta, timesheet }),
+        None => serde_json::to_value(timesheet),
+    }
+    .context("序列化 JSON 失败")?;
+
+    if cell_as_hex {
+        let frames = value["layers"]
+            .as_array_mut()
+            .into_iter()
+            .flatten()
+            .filter_map(|layer| layer["frames"].as_array_mut())
+            .flatten();
+        for frame in frames {
+            if let Some(cell) = frame["cell"].as_u64().filter(|&cell| cell != 0) {
+                frame["cell"] = format!("0x{:X}", cell).into();
+            }
+        }
+    }
+
+    serde_json::to_string_pretty(&value).context("序列化 JSON 失败")
+}
+
+/// `--metadata-only` 输出的时间表概要
+#[derive(Serialize)]
+struct TimesheetMetadata<'a> {
+    name: &'a str,
+    frame_count: u32,
+    layer_count: usize,
+    layer_names: Vec<&'a str>,
+}
+
+/// 将一个源文件中所有时间表的概要（不含帧数据）写为 JSON 数组
+pub fn save_metadata(timesheets: &[Timesheet], output_path: &Path) -> Result<()> {
+    let metadata: Vec<TimesheetMetadata> = timesheets
+        .iter()
+        .map(|ts| TimesheetMetadata {
+            name: &ts.name,
+            frame_count: ts.frame_count,
+            layer_count: ts.layers.len(),
+            layer_names: ts.layers.iter().map(|layer| layer.name.as_str()).collect(),
+        })
+        .collect();
+    let text = serde_json::to_string_pretty(&metadata).context("序列化 JSON 失败")?;
+
+    std::fs::write(output_path, text)
+        .with_context(|| format!("写入文件失败: {}", output_path.display()))
+}
+
+/// `--format json` 输出的 JSON Schema
+pub fn timesheet_schema() -> Result<String> {
+    let schema = schemars::schema_for!(Timesheet);
+    serde_json::to_string_pretty(&schema).context("序列化 JSON Schema 失败")
+}
+
+/// `--info` 输出的版本和格式信息
+#[derive(Serialize)]
+struct ToolInfo {
+    name: &'static str,
+    version: &'static str,
+    input_extensions: [&'static str; 2],
+    output_formats: Vec<OutputFormatInfo>,
+    sts: StsInfo,
+    /// `--split-cuts` 写出的 XDTS 版本
+    xdts_version: u32,
+}
+
+#[derive(Serialize)]
+struct OutputFormatInfo {
+    name: &'static str,
+    extension: &'static str,
+}
+
+/// 可写出的 STS 变体（STS 文件头中没有版本号）
+#[derive(Serialize)]
+struct StsInfo {
+    /// `--cell-width` 可选的字节数
+    cell_widths: Vec<usize>,
+    /// 层名称区布局：`[长度][名称]` 或 `--name-field-width` 的固定宽度
+    name_layouts: [&'static str; 2],
+}
+
+/// `--info` 输出：工具版本、支持的输入扩展名和输出格式
+pub fn tool_info() -> Result<String> {
+    let info = ToolInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        input_extensions: ["xdts", "tdts"],
+        output_formats: OutputFormat::ALL
+            .into_iter()
+            .map(|format| OutputFormatInfo {
+                name: format.name(),
+                extension: format.extension(),
+            })
+            .collect(),
+        sts: StsInfo {
+            cell_widths: [CellWidth::U8, CellWidth::U16, CellWidth::U32]
+                .into_iter()
+                .map(CellWidth::bytes)
+                .collect(),
+            name_layouts: ["length-prefixed", "fixed-width"],
+        },
+        xdts_version: crate::xdts::XDTS_VERSION,
+    };
+    serde_json::to_string_pretty(&info).context("序列化 JSON 失败")
+}
+
+/// `--list-fields --json` 中一个源文件的结构
+#[derive(Serialize)]
+struct FileFields<'a> {
+    file: &'a str,
+    format: &'static str,
+    tables: &'a [TableFields],
+}
+
+/// `--list-fields --json` 输出：每个源文件中各时间表的 field 和 header 结构
+pub fn fields_json(files: &[(String, SourceFormat, Vec<TableFields>)]) -> Result<String> {
+    let files: Vec<FileFields> = files
+        .iter()
+        .map(|(file, format, tables)| FileFields {
+            file,
+            format: format.name(),
+            tables,
+        })
+        .collect();
+    serde_json::to_string_pretty(&files).context("序列化 JSON 失败")
+}
@@ -0,0 +1,42 @@
+//! XDTS/TDTS 转 STS 的转换库
+//!
+//! 命令行程序（`main.rs`）只是这些模块的外壳，其他工具可以直接调用：
+//!
+//! ```no_run
+//! use xdts2sts::{load_timesheets, save_sts, Options, Warnings};
+//! use std::path::Path;
+//!
+//! let opts = Options::default();
+//! let mut warnings = Warnings::default();
+//! let (_, sheets) = load_timesheets(Path::new("cut.xdts"), &opts, &mut warnings)?;
+//! for sheet in &sheets {
+//!     save_sts(sheet, Path::new("out.sts"), false, &opts, &mut warnings)?;
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod archive;
+pub mod cli;
+pub mod config;
+pub mod container;
+pub mod converter;
+pub mod index;
+pub mod json;
+pub mod longcsv;
+pub mod options;
+pub mod otcsv;
+pub mod parser;
+pub mod pipeline;
+pub mod preview;
+pub mod provenance;
+pub mod state;
+pub mod sts;
+pub mod types;
+pub mod warning;
+pub mod xdts;
+
+pub use converter::save_sts;
+pub use options::Options;
+pub use parser::load_timesheets;
+pub use types::{Frame, Layer, Timesheet};
+pub use warning::{Warning, Warnings};
@@ -0,0 +1,75 @@
+//! 长格式 CSV 导出（每帧每层一行，便于透视表统计）
+//!
+//! 布局:
+//!
+//! ```text
+//! frame,layer_name,cell
+//! 1,A,1
+//! 1,B,0       ← 0 为空白；`--skip-blank-rows` 时省略
+//! 2,A,1       ← 保持的帧默认重复编号；`--hold-style blank|bar` 时为空或 `|`
+//! ```
+
+use crate::converter::{expand_timesheet, ConvertReport};
+use crate::options::{HoldStyle, Options};
+use crate::otcsv::{csv_field, HOLD_BAR};
+use crate::provenance::Provenance;
+use crate::types::Timesheet;
+use crate::warning::Warnings;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+pub fn save_long_csv(
+    timesheet: &Timesheet,
+    output_path: &Path,
+    verbose: bool,
+    opts: &Options,
+    provenance: Option<&Provenance>,
+    warnings: &mut Warnings,
+) -> Result<ConvertReport> {
+    let (mut text, report) = encode_long_csv(timesheet, verbose, opts, warnings)?;
+
+    if let Some(provenance) = provenance {
+        text.insert_str(0, &format!("{}\n", provenance.comment_line()));
+    }
+
+    std::fs::write(output_path, text)
+        .with_context(|| format!("写入文件失败: {}", output_path.display()))?;
+
+    if verbose {
+        println!("\n文件已生成: {}", output_path.display());
+    }
+
+    Ok(report)
+}
+
+/// 将展开后的时间表编码为 `frame,layer_name,cell` 长格式 CSV 文本
+pub fn encode_long_csv(
+    timesheet: &Timesheet,
+    verbose: bool,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<(String, ConvertReport)> {
+    let (all_layers_cells, report) = expand_timesheet(timesheet, verbose, opts, warnings)?;
+
+    let names: Vec<_> = timesheet.layers.iter().map(|layer| csv_field(&layer.name)).collect();
+
+    let hold_style = opts.hold_style.unwrap_or(HoldStyle::Repeat);
+    let mut text = String::from("frame,layer_name,cell\n");
+    for frame in 0..report.frame_count {
+        for (name, cells) in names.iter().zip(&all_layers_cells) {
+            let cell = cells[frame];
+            if cell == 0 && opts.skip_blank_rows {
+                continue;
+            }
+            let held = cell != 0 && frame > 0 && cells[frame - 1] == cell;
+            let value = match hold_style {
+                HoldStyle::Blank if held => String::new(),
+                HoldStyle::Bar if held => HOLD_BAR.to_string(),
+                _ => opts.format_cell(cell),
+            };
+            text.push_str(&format!("{},{},{}\n", frame + 1, name, value));
+        }
+    }
+
+    Ok((text, report))
+}
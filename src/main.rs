@@ -1,13 +1,24 @@
 #![cfg_attr(windows, windows_subsystem = "windows")]
 
-mod converter;
-mod parser;
-mod types;
+use xdts2sts::{
+    archive, config, converter, index, json, longcsv, options, otcsv, parser, pipeline, state, sts,
+    types, warning,
+};
 
 use anyhow::{Context, Result};
+use options::{Options, OutputFormat};
+use pipeline::{
+    format_number, is_container_file, is_sts_file, preprocess_timesheets, process_file_with_timeout,
+    Failure, OutputPaths, ProcessOutcome,
+};
+use rayon::prelude::*;
 use std::env;
-use std::io;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use types::SourceFormat;
+use warning::Warnings;
 use walkdir::WalkDir;
 
 #[cfg(windows)]
@@ -15,7 +26,9 @@ use winapi::um::consoleapi::AllocConsole;
 #[cfg(windows)]
 use winapi::um::wincon::{GetConsoleWindow};
 #[cfg(windows)]
-use winapi::um::winuser::{MessageBoxW, MB_ICONINFORMATION, MB_ICONERROR, MB_OK};
+use winapi::um::winuser::{
+    MessageBoxW, IDOK, MB_ICONERROR, MB_ICONINFORMATION, MB_ICONQUESTION, MB_OK, MB_OKCANCEL,
+};
 
 #[cfg(windows)]
 fn allocate_console() -> bool {
@@ -55,64 +68,173 @@ fn show_message_box(title: &str, message: &str, is_error: bool) {
     }
 }
 
+/// 显示确认对话框，用户点击“确定”时返回 true
+#[cfg(windows)]
+fn confirm_message_box(title: &str, message: &str) -> bool {
+    use std::ffi::OsStr;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+
+    let title_wide: Vec<u16> = OsStr::new(title)
+        .encode_wide()
+        .chain(once(0))
+        .collect();
+    let message_wide: Vec<u16> = OsStr::new(message)
+        .encode_wide()
+        .chain(once(0))
+        .collect();
+
+    unsafe {
+        MessageBoxW(
+            std::ptr::null_mut(),
+            message_wide.as_ptr(),
+            title_wide.as_ptr(),
+            MB_OKCANCEL | MB_ICONQUESTION,
+        ) == IDOK
+    }
+}
+
 #[cfg(not(windows))]
 fn allocate_console() -> bool {
     true
 }
 
 #[cfg(not(windows))]
-fn free_console() {}
+fn show_message_box(title: &str, message: &str, is_error: bool) {
+    if is_error {
+        eprintln!("{}: {}", title, message);
+    } else {
+        println!("{}: {}", title, message);
+    }
+}
 
 #[cfg(not(windows))]
-fn show_message_box(title: &str, message: &str, _is_error: bool) {
+fn confirm_message_box(title: &str, message: &str) -> bool {
     println!("{}: {}", title, message);
+    println!("继续? [y/N]");
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+fn main() {
+    match run() {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            let error_msg = format!("转换过程中发生错误：\n\n{}", e);
+            show_message_box("错误", &error_msg, true);
+            std::process::exit(1);
+        }
+    }
 }
 
-/// 格式化数字为带千位分隔符的字符串
-fn format_number(n: u64) -> String {
-    let s = n.to_string();
-    let mut result = String::new();
-    let mut count = 0;
+/// 运行转换，返回进程退出码（有转换失败时非零）
+fn run() -> Result<i32> {
+    let started = Instant::now();
+    let args = expand_arg_files(env::args().skip(1))?;
+
+    let (opts, inputs) = match Options::parse_args(&args) {
+        Ok(parsed) => parsed,
+        // --help / --version 不是错误，直接打印 clap 生成的说明
+        Err(e) => match e.downcast_ref::<clap::Error>() {
+            Some(e) if !e.use_stderr() => {
+                e.print()?;
+                return Ok(0);
+            }
+            _ => return Err(e),
+        },
+    };
+
+    if opts.info {
+        println!("{}", json::tool_info()?);
+        return Ok(0);
+    }
+
+    if opts.json_schema {
+        println!("{}", json::timesheet_schema()?);
+        return Ok(0);
+    }
 
-    for c in s.chars().rev() {
-        if count > 0 && count % 3 == 0 {
-            result.push(',');
+    // 只列出 STS 文件中的层名称
+    if opts.names {
+        for (i, arg) in inputs.iter().enumerate() {
+            let sts = sts::load_sts(Path::new(arg))?;
+            if inputs.len() > 1 {
+                if i > 0 {
+                    println!();
+                }
+                println!("{}:", arg);
+            }
+            for (idx, layer) in sts.layers.iter().enumerate() {
+                println!("{}: {}", idx + 1, layer.name);
+            }
         }
-        result.push(c);
-        count += 1;
+        return Ok(0);
     }
 
-    result.chars().rev().collect()
-}
+    // 比较两个源文件的曝光变化
+    if let Some((old_path, new_path)) = &opts.source_diff {
+        print_source_diff(old_path, new_path, &opts)?;
+        return Ok(0);
+    }
 
-fn main() {
-    if let Err(e) = run() {
-        let error_msg = format!("转换过程中发生错误：\n\n{}", e);
-        show_message_box("错误", &error_msg, true);
-        std::process::exit(1);
+    // 自检：目录中每个源文件编码为 STS 再读回，与展开结果比较
+    if let Some(dir) = &opts.round_trip_check {
+        return round_trip_check(dir, &opts);
+    }
+
+    // 只列出源文件中的 field 结构
+    if opts.list_fields {
+        let mut files = Vec::new();
+        for arg in &inputs {
+            let (format, tables) = parser::list_fields(Path::new(arg), &opts)?;
+            files.push((arg.clone(), format, tables));
+        }
+        if opts.json {
+            println!("{}", json::fields_json(&files)?);
+        } else {
+            print_fields(&files);
+        }
+        return Ok(0);
     }
-}
 
-fn run() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+    // 管道模式：从标准输入读取，结果写到标准输出
+    if opts.from_stdin {
+        if let Some(input) = inputs.first() {
+            anyhow::bail!("--from-stdin 不能同时指定输入路径: {}", input);
+        }
+        return convert_stdin(&opts);
+    }
 
-    // 检查是否有参数
-    if args.len() < 2 {
+    // 检查是否有输入路径
+    if inputs.is_empty() {
         print_usage();
-        return Ok(());
+        return Ok(0);
+    }
+
+    // 多文件和单个文件内多时间表的并行转换共用同一个线程池；
+    // --verbose 的多行输出并行时会互相交错，因此改为串行处理
+    let threads = if opts.verbose { Some(1) } else { opts.threads };
+    if let Some(threads) = threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("无法创建线程池")?;
     }
 
     // 获取 exe 所在目录
     let exe_dir = get_exe_dir()?;
 
-    let mut all_output_paths = Vec::new();
-    let mut total_files = 0;
+    let mut summary = BatchSummary::default();
 
     // 收集所有有效的文件和文件夹
     let mut valid_files = Vec::new();
     let mut valid_folders = Vec::new();
 
-    for arg in &args[1..] {
+    for arg in &inputs {
         let input_path = PathBuf::from(arg);
 
         if !input_path.exists() {
@@ -121,13 +243,10 @@ fn run() -> Result<()> {
         }
 
         if input_path.is_file() {
-            let ext = input_path
-                .extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s.to_lowercase())
-                .unwrap_or_default();
-
-            if ext == "xdts" || ext == "tdts" {
+            if opts.accepts_file(&input_path)
+                || is_container_file(&input_path)
+                || is_sts_file(&input_path)
+            {
                 valid_files.push(input_path);
             }
         } else if input_path.is_dir() {
@@ -135,56 +254,115 @@ fn run() -> Result<()> {
         }
     }
 
+    // 参数都不存在或不是支持的文件时明确报错，而不是什么都不做
+    if valid_files.is_empty() && valid_folders.is_empty() {
+        show_message_box("错误", "没有可处理的 xdts/tdts 文件", true);
+        return Ok(1);
+    }
+
     // 判断是否为单文件模式（只有1个文件，没有文件夹）
     let is_single_file_mode = valid_files.len() == 1 && valid_folders.is_empty();
 
-    // 多文件/文件夹模式：分配控制台显示进度
-    if !is_single_file_mode {
-        if !allocate_console() {
-            // 无法分配控制台，改用消息框
-            show_message_box(
-                "错误",
-                "无法创建控制台窗口",
-                true,
-            );
-            return Ok(());
+    // 文件夹的输出目录：--output-dir 或 exe 同目录下的 converted_sts
+    let output_dir = opts
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| exe_dir.join("converted_sts"));
+
+    // 开始前检查一次输出目录是否可写，避免每个文件都报同样的错误
+    if !opts.validate_only && !opts.dry_run {
+        if let Some(dir) = &opts.output_dir {
+            check_writable(dir)?;
+        } else if !valid_folders.is_empty() {
+            check_writable(&output_dir)?;
         }
     }
 
+    let batch = Batch {
+        // 断点续转：跳过状态文件中已完成的源文件，每完成一个追加记录
+        state: opts
+            .state_file
+            .as_deref()
+            .map(state::StateFile::open)
+            .transpose()?,
+        outputs: OutputPaths::default(),
+    };
+
+    // 多文件/文件夹模式：分配控制台显示进度
+    if !is_single_file_mode && !allocate_console() {
+        // 无法分配控制台，改用消息框
+        show_message_box(
+            "错误",
+            "无法创建控制台窗口",
+            true,
+        );
+        return Ok(1);
+    }
+
     // 处理单独拖放的文件
-    if !valid_files.is_empty() {
+    if valid_files.len() > 1 {
+        // 多个文件并行转换，完成一个打印一行进度
+        println!("\n{}", "=".repeat(60));
+        println!("处理 {} 个文件", valid_files.len());
+        println!("{}", "=".repeat(60));
+
+        let done = AtomicUsize::new(0);
+        let results: Vec<Result<ProcessOutcome>> = valid_files
+            .par_iter()
+            .map(|input_path| {
+                let result = process_file_resumable(
+                    input_path,
+                    opts.output_dir.as_deref(),
+                    true,
+                    false,
+                    &opts,
+                    &batch,
+                    None,
+                );
+                let finished = done.fetch_add(1, Ordering::SeqCst) + 1;
+                let file_name = input_path.file_name().unwrap().to_string_lossy();
+                print_progress(finished, valid_files.len(), &file_name, &result, opts.quiet);
+                result
+            })
+            .collect();
+
+        // 按输入顺序汇总，保证结果与串行处理一致
+        for (input_path, result) in valid_files.iter().zip(results) {
+            if let Ok(outcome) = &result {
+                for line in &outcome.notes {
+                    println!("{}", line);
+                }
+            }
+            summary.record(input_path, result);
+        }
+    } else if let Some(input_path) = valid_files.first() {
         if !is_single_file_mode {
             println!("\n{}", "=".repeat(60));
-            println!("处理 {} 个文件", valid_files.len());
+            println!("处理 1 个文件: {}", input_path.file_name().unwrap().to_string_lossy());
             println!("{}", "=".repeat(60));
         }
 
-        for (idx, input_path) in valid_files.iter().enumerate() {
-            if !is_single_file_mode {
-                println!(
-                    "\n[{}/{}] {}",
-                    idx + 1,
-                    valid_files.len(),
-                    input_path.file_name().unwrap().to_string_lossy()
-                );
-                println!("{}", "-".repeat(60));
-            }
-
-            match process_file(&input_path, None, false, is_single_file_mode) {
-                Ok(output_paths) => {
-                    all_output_paths.extend(output_paths.clone());
-                    total_files += 1;
-                    if !is_single_file_mode && valid_files.len() > 1 {
-                        println!("✓ 完成 ({} 个 STS 文件)", output_paths.len());
-                    }
-                }
-                Err(e) => {
-                    if !is_single_file_mode {
-                        eprintln!("✗ 转换失败: {}", e);
-                    }
+        // --confirm 只在单文件模式下生效
+        let confirm = is_single_file_mode && opts.confirm;
+        let result = process_file_resumable(
+            input_path,
+            opts.output_dir.as_deref(),
+            is_single_file_mode,
+            confirm,
+            &opts,
+            &batch,
+            None,
+        );
+        match &result {
+            Ok(outcome) if outcome.cancelled => return Ok(0),
+            Ok(_) => {}
+            Err(e) => {
+                if !is_single_file_mode {
+                    eprintln!("✗ 转换失败: {}", e);
                 }
             }
         }
+        summary.record(input_path, result);
     }
 
     // 处理拖放的文件夹
@@ -202,183 +380,599 @@ fn run() -> Result<()> {
         }
         println!("{}", "=".repeat(60));
 
-        let timesheet_files = find_timesheet_files(&input_path)?;
+        // 文件数超过上限（如误拖了整个磁盘）时，找到第 max_files + 1 个文件就停止扫描，
+        // 确认后再扫描完整个文件夹
+        let limit = (!opts.yes).then(|| opts.max_files());
+        let mut timesheet_files = find_timesheet_files(input_path, &opts, limit)?;
 
         if timesheet_files.is_empty() {
             println!("未找到 .xdts 或 .tdts 文件");
             continue;
         }
 
-        println!("找到 {} 个文件:", timesheet_files.len());
-        for f in &timesheet_files {
-            println!("  - {}", f.file_name().unwrap().to_string_lossy());
+        if limit.is_some_and(|max| timesheet_files.len() > max) {
+            let message = format!(
+                "文件夹 {} 中的待转换文件超过上限 {} 个。\n确定要继续扫描并全部转换吗？",
+                input_path.display(),
+                opts.max_files()
+            );
+            if !confirm_message_box("文件过多", &message) {
+                println!("已跳过 (可用 --yes 跳过确认，或用 --max-files 调整上限)");
+                continue;
+            }
+            timesheet_files = find_timesheet_files(input_path, &opts, None)?;
+        }
+
+        if opts.quiet {
+            println!("找到 {} 个文件", timesheet_files.len());
+        } else {
+            println!("找到 {} 个文件:", timesheet_files.len());
+            for f in &timesheet_files {
+                println!("  - {}", f.strip_prefix(input_path).unwrap_or(f).display());
+            }
+            println!();
         }
-        println!();
 
-        // 在 exe 同目录下创建 converted_sts 文件夹
-        let output_dir = exe_dir.join("converted_sts");
-        std::fs::create_dir_all(&output_dir)
-            .context("无法创建输出目录")?;
+        // 各文件并行转换，完成一个打印一行进度
+        let done = AtomicUsize::new(0);
+        let results: Vec<Result<ProcessOutcome>> = timesheet_files
+            .par_iter()
+            .map(|ts_file| {
+                let relative = ts_file.strip_prefix(input_path).unwrap_or(ts_file);
 
-        // 转换每个文件
-        for (idx, ts_file) in timesheet_files.iter().enumerate() {
-            println!("{}", "-".repeat(60));
-            println!(
-                "[{}/{}] 正在处理: {}",
-                idx + 1,
-                timesheet_files.len(),
-                ts_file.file_name().unwrap().to_string_lossy()
-            );
+                // 递归扫描时按源文件所在的子文件夹输出，不同子文件夹中的同名文件不会互相覆盖
+                let file_output_dir = match relative.parent() {
+                    Some(subdir) if !subdir.as_os_str().is_empty() => output_dir.join(subdir),
+                    _ => output_dir.clone(),
+                };
+                let writes = !opts.dry_run && !opts.validate_only;
+                let create_dir = if file_output_dir != output_dir && writes {
+                    std::fs::create_dir_all(&file_output_dir).with_context(|| {
+                        format!("无法创建输出目录: {}", file_output_dir.display())
+                    })
+                } else {
+                    Ok(())
+                };
 
-            match process_file(&ts_file, Some(&output_dir), false, false) {
-                Ok(output_paths) => {
-                    all_output_paths.extend(output_paths.clone());
-                    total_files += 1;
-                    println!("✓ 完成 ({} 个 STS 文件)", output_paths.len());
-                }
-                Err(e) => {
-                    eprintln!("✗ 转换失败: {}", e);
+                let result = create_dir.and_then(|_| {
+                    process_file_resumable(
+                        ts_file,
+                        Some(&file_output_dir),
+                        true,
+                        false,
+                        &opts,
+                        &batch,
+                        Some(input_path),
+                    )
+                });
+                let finished = done.fetch_add(1, Ordering::SeqCst) + 1;
+                let file_name = relative.display().to_string();
+                print_progress(finished, timesheet_files.len(), &file_name, &result, opts.quiet);
+                result
+            })
+            .collect();
+
+        // 按文件顺序汇总，保证结果与串行处理一致
+        for (ts_file, result) in timesheet_files.iter().zip(results) {
+            if let Ok(outcome) = &result {
+                for line in &outcome.notes {
+                    println!("{}", line);
                 }
             }
+            summary.record(ts_file, result);
+        }
+    }
+
+    // 汇总索引写入输出目录；单独拖放的文件没有统一的输出目录时写在第一个文件旁
+    if opts.index_csv && !opts.dry_run && !summary.index_rows.is_empty() {
+        let index_dir = if opts.output_dir.is_some() || !valid_folders.is_empty() {
+            output_dir.clone()
+        } else {
+            summary.index_rows[0]
+                .output
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        };
+        let index_path = index_dir.join("index.csv");
+        index::save_index_csv(&summary.index_rows, &index_path)?;
+        summary.output_paths.push(index_path);
+    }
+
+    // 把本次生成的文件打包为 zip
+    if let Some(zip_path) = &opts.zip_output {
+        if !summary.output_paths.is_empty() {
+            let paths: Vec<PathBuf> = summary
+                .output_paths
+                .iter()
+                .chain(&summary.sidecar_paths)
+                .cloned()
+                .collect();
+            archive::save_zip(&paths, zip_path)?;
+            summary.output_paths.push(zip_path.clone());
         }
     }
 
     // 单文件模式：用消息框显示结果
+    if is_single_file_mode && opts.validate_only && summary.total_files > 0 {
+        let message = if summary.warnings.is_empty() {
+            "校验通过，未发现问题。".to_string()
+        } else {
+            summary
+                .warnings
+                .iter()
+                .flat_map(|(_, warnings)| warnings.iter())
+                .map(|warning| warning.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        show_message_box("校验结果", &message, !summary.warnings.is_empty());
+        return Ok(summary.exit_code());
+    }
+    if is_single_file_mode && opts.dry_run && summary.total_files > 0 {
+        show_message_box("试运行", &summary.notes.join("\n"), !summary.failures.is_empty());
+        return Ok(summary.exit_code());
+    }
+    if is_single_file_mode && opts.explain.is_some() && !summary.notes.is_empty() {
+        show_message_box("单元格推导", &summary.notes.join("\n"), false);
+    }
     if is_single_file_mode {
-        if total_files > 0 && !all_output_paths.is_empty() {
-            for path in &all_output_paths {
+        if summary.total_files > 0
+            && (!summary.output_paths.is_empty()
+                || summary.skipped_current > 0
+                || summary.skipped_done > 0)
+        {
+            for path in &summary.output_paths {
                 let _size = std::fs::metadata(path)?.len();
                 let _file_name = path.file_name().unwrap().to_string_lossy();
             }
         } else {
             show_message_box("转换失败", "文件转换失败，请检查文件格式。", true);
         }
-        return Ok(());
+        return Ok(summary.exit_code());
     }
 
     // 多文件/文件夹模式：显示详细总结
-    println!("\n{}", "=".repeat(60));
-    println!("转换完成!");
-    println!("{}", "=".repeat(60));
-    println!("处理了 {} 个源文件", total_files);
-    println!("生成了 {} 个 STS 文件", all_output_paths.len());
-
-    if !all_output_paths.is_empty() {
-        println!("\n生成的文件:");
-        for path in all_output_paths.iter().take(10) {
-            let size = std::fs::metadata(path)?.len();
-            println!(
-                "  - {} ({} 字节)",
-                path.file_name().unwrap().to_string_lossy(),
-                format_number(size)
-            );
+    summary.print(!opts.quiet_warnings, started.elapsed())?;
+
+    // 只在直接拖放（参数全部是输入路径）到控制台时等待按键；带选项或在脚本、CI 中运行时直接退出
+    if args.len() == inputs.len() && io::stdin().is_terminal() {
+        println!("\n按任意键退出...");
+        let _ = io::stdin().read_line(&mut String::new());
+    }
+
+    Ok(summary.exit_code())
+}
+
+/// 按时间表名称（去掉源文件名部分）对齐两个源文件，逐层列出变化的帧范围
+fn print_source_diff(old_path: &Path, new_path: &Path, opts: &Options) -> Result<()> {
+    let (_, old) = parser::load_timesheets(old_path, opts, &mut Warnings::default())?;
+    let (_, new) = parser::load_timesheets(new_path, opts, &mut Warnings::default())?;
+
+    // 名称为 "源文件名->[cut->]时间表名"，两个文件名不同，只比较后面的部分
+    let table_name = |ts: &types::Timesheet| {
+        ts.name
+            .split_once("->")
+            .map_or(ts.name.clone(), |(_, rest)| rest.to_string())
+    };
+
+    let mut unchanged = 0;
+    for new_ts in &new {
+        let name = table_name(new_ts);
+        let Some(old_ts) = old.iter().find(|ts| table_name(ts) == name) else {
+            println!("时间表 '{}': 新增", name);
+            continue;
+        };
+        let lines = converter::diff_timesheets(old_ts, new_ts, opts)?;
+        if lines.is_empty() {
+            unchanged += 1;
+            continue;
         }
-        if all_output_paths.len() > 10 {
-            println!("  ... 还有 {} 个文件", all_output_paths.len() - 10);
+        println!("时间表 '{}':", name);
+        for line in lines {
+            println!("  {}", line);
         }
     }
-
-    println!("\n按任意键退出...");
-    let _ = io::stdin().read_line(&mut String::new());
+    for old_ts in &old {
+        let name = table_name(old_ts);
+        if !new.iter().any(|ts| table_name(ts) == name) {
+            println!("时间表 '{}': 已删除", name);
+        }
+    }
+    if unchanged > 0 {
+        println!("未变化的时间表: {} 个", unchanged);
+    }
 
     Ok(())
 }
 
-fn process_file(
-    input_path: &Path,
-    output_dir: Option<&Path>,
-    verbose: bool,
-    quiet: bool,
-) -> Result<Vec<PathBuf>> {
-    // 加载时间表
-    if !verbose && !quiet {
-        println!("正在加载: {}", input_path.display());
-    }
+/// 把标准输入中的单个时间表转换为文本格式写到标准输出，警告写到标准错误
+fn convert_stdin(opts: &Options) -> Result<i32> {
+    let mut warnings = Warnings::default();
+    let (_, mut timesheets) = parser::load_timesheets_from_stdin(opts, &mut warnings)?;
+    preprocess_timesheets(&mut timesheets, opts, &mut warnings)?;
 
-    let timesheets = parser::load_timesheets(input_path)?;
+    let ts = match timesheets.as_slice() {
+        [ts] => ts,
+        _ => anyhow::bail!(
+            "--stdout 只支持单个时间表，输入中有 {} 个: {}",
+            timesheets.len(),
+            timesheets.iter().map(|ts| ts.name.as_str()).collect::<Vec<_>>().join(", ")
+        ),
+    };
 
-    if !verbose && !quiet {
-        println!("找到 {} 个时间表", timesheets.len());
+    let text = match opts.format {
+        OutputFormat::Otcsv => otcsv::encode_otcsv(ts, false, opts, &mut warnings)?.0,
+        OutputFormat::LongCsv => longcsv::encode_long_csv(ts, false, opts, &mut warnings)?.0,
+        OutputFormat::Json => json::encode_json(ts, None, opts.cell_as_hex)?,
+        OutputFormat::Sts => unreachable!("--stdout 不支持 sts，解析选项时已检查"),
+    };
+
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(text.as_bytes())
+        .and_then(|_| stdout.flush())
+        .context("写入标准输出失败")?;
+
+    if !opts.quiet_warnings {
+        for warning in warnings.iter() {
+            eprintln!("警告: {}", warning);
+        }
     }
+    Ok(0)
+}
 
-    // 确定输出目录
-    let output_dir = match output_dir {
-        Some(dir) => dir.to_path_buf(),
-        None => input_path
-            .parent()
-            .context("无法获取父目录")?
-            .to_path_buf(),
-    };
+/// 对目录中的所有源文件做往返检查，逐个打印结果，有失败时返回 1
+fn round_trip_check(dir: &Path, opts: &Options) -> Result<i32> {
+    let files = find_timesheet_files(dir, opts, None)?;
+    if files.is_empty() {
+        anyhow::bail!("目录中没有 xdts/tdts 文件: {}", dir.display());
+    }
 
-    let mut output_paths = Vec::new();
+    let mut failed = 0;
+    for path in &files {
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let errors = match parser::load_timesheets(path, opts, &mut Warnings::default()) {
+            Ok((_, timesheets)) => timesheets
+                .iter()
+                .filter_map(|ts| {
+                    converter::check_round_trip(ts, opts)
+                        .err()
+                        .map(|e| format!("{}: {:#}", ts.name, e))
+                })
+                .collect(),
+            Err(e) => vec![format!("{:#}", e)],
+        };
 
-    // 转换每个时间表
-    for (i, ts) in timesheets.iter().enumerate() {
-        // 生成输出文件名
-        let output_name = if timesheets.len() == 1 {
-            format!(
-                "{}.sts",
-                input_path.file_stem().unwrap().to_string_lossy()
-            )
+        if errors.is_empty() {
+            println!("✓ {}", file_name);
         } else {
-            let safe_name = ts
-                .name
-                .replace('/', "_")
-                .replace('\\', "_")
-                .replace(':', "_");
-            let safe_name = if safe_name.len() > 100 {
-                &safe_name[..100]
-            } else {
-                &safe_name
+            failed += 1;
+            println!("✗ {}", file_name);
+            for error in errors {
+                println!("    {}", error);
+            }
+        }
+    }
+
+    println!(
+        "\n往返检查: {} 个文件，{} 个通过，{} 个失败",
+        files.len(),
+        files.len() - failed,
+        failed
+    );
+    Ok(if failed > 0 { 1 } else { 0 })
+}
+
+/// 以文本列出各时间表的 field 和 header 结构
+fn print_fields(files: &[(String, SourceFormat, Vec<parser::TableFields>)]) {
+    let describe = |id: Option<u32>, name: &Option<String>| match (id, name) {
+        (Some(id), Some(name)) => format!("fieldId {} '{}'", id, name),
+        (Some(id), None) => format!("fieldId {}", id),
+        (None, Some(name)) => format!("'{}'", name),
+        (None, None) => "(无 id)".to_string(),
+    };
+
+    for (i, (file, format, tables)) in files.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{} ({}):", file, format.name());
+        for table in tables {
+            println!("  {}", table.table);
+            let fields: Vec<String> = table
+                .fields
+                .iter()
+                .map(|f| format!("{} ({} 个 track)", describe(f.field_id, &f.field_name), f.tracks))
+                .collect();
+            let headers: Vec<String> = table
+                .headers
+                .iter()
+                .map(|h| format!("{} ({} 个名称)", describe(h.field_id, &h.field_name), h.names))
+                .collect();
+            let join = |items: Vec<String>| {
+                if items.is_empty() {
+                    "无".to_string()
+                } else {
+                    items.join(", ")
+                }
             };
-            format!(
-                "{}_{:03}_{}.sts",
-                input_path.file_stem().unwrap().to_string_lossy(),
-                i,
-                safe_name
-            )
+            println!("    fields: {}", join(fields));
+            println!("    headers: {}", join(headers));
+        }
+    }
+}
+
+/// 展开 `@文件` 形式的参数：文件中每行一个参数，空行忽略
+fn expand_arg_files(args: impl Iterator<Item = String>) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+
+    for arg in args {
+        let Some(arg_file) = arg.strip_prefix('@') else {
+            expanded.push(arg);
+            continue;
         };
 
-        let output_path = output_dir.join(output_name);
-
-        // 转换并保存
-        match converter::save_sts(ts, &output_path, verbose) {
-            Ok(_) => {
-                output_paths.push(output_path.clone());
-                if !verbose && !quiet {
-                    println!(
-                        "✓ 已转换: {}",
-                        output_path.file_name().unwrap().to_string_lossy()
-                    );
+        let content = std::fs::read_to_string(arg_file)
+            .with_context(|| format!("无法读取参数文件: {}", arg_file))?;
+        expanded.extend(
+            content
+                .trim_start_matches('\u{feff}')
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string),
+        );
+    }
+
+    Ok(expanded)
+}
+
+/// 整个批处理的汇总
+#[derive(Debug, Default)]
+struct BatchSummary {
+    /// 成功处理的源文件数
+    total_files: usize,
+    /// 生成的输出文件
+    output_paths: Vec<PathBuf>,
+    /// 生成的预览图等附带文件
+    sidecar_paths: Vec<PathBuf>,
+    /// 因输出已是最新而跳过的文件数
+    skipped_current: usize,
+    /// `--state-file` 中已完成而跳过的源文件数
+    skipped_done: usize,
+    /// 所有失败记录
+    failures: Vec<Failure>,
+    /// 按源文件分组的警告
+    warnings: Vec<(PathBuf, Warnings)>,
+    /// `--index-csv` 的索引行
+    index_rows: Vec<index::IndexRow>,
+    /// 各源文件的说明（写入计划、变化的层）
+    notes: Vec<String>,
+    /// 按源格式分别统计
+    xdts: FormatTally,
+    tdts: FormatTally,
+    /// 已处理源文件的总字节数（用于计算吞吐量）
+    input_bytes: u64,
+}
+
+/// 一种源格式的源文件数和生成的文件数
+#[derive(Debug, Default)]
+struct FormatTally {
+    files: usize,
+    outputs: usize,
+}
+
+impl BatchSummary {
+    /// 记录一个源文件的处理结果
+    fn record(&mut self, input_path: &Path, result: Result<ProcessOutcome>) {
+        match result {
+            Ok(outcome) if outcome.already_done => self.skipped_done += 1,
+            Ok(outcome) => {
+                self.total_files += 1;
+                self.input_bytes += std::fs::metadata(input_path).map_or(0, |m| m.len());
+                if let Some(format) = outcome.source_format {
+                    let tally = match format {
+                        SourceFormat::Xdts => &mut self.xdts,
+                        SourceFormat::Tdts => &mut self.tdts,
+                    };
+                    tally.files += 1;
+                    tally.outputs += outcome.output_paths.len();
+                }
+                self.skipped_current += outcome.skipped_current;
+                self.output_paths.extend(outcome.output_paths);
+                self.sidecar_paths.extend(outcome.sidecar_paths);
+                self.failures.extend(outcome.failures);
+                self.index_rows.extend(outcome.index_rows);
+                self.notes.extend(outcome.notes);
+                if !outcome.warnings.is_empty() {
+                    self.warnings.push((input_path.to_path_buf(), outcome.warnings));
                 }
             }
-            Err(e) => {
-                if !quiet {
-                    eprintln!("✗ 转换失败: {}", ts.name);
-                    eprintln!("  错误: {}", e);
+            Err(e) => self.failures.push(Failure::new(input_path, None, &e)),
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        if self.failures.is_empty() {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// 打印批处理总结；`show_warnings` 为 false 时不列出警告
+    fn print(&self, show_warnings: bool, elapsed: Duration) -> Result<()> {
+        println!("\n{}", "=".repeat(60));
+        println!("转换完成!");
+        println!("{}", "=".repeat(60));
+        println!("处理了 {} 个源文件", self.total_files);
+        println!("生成了 {} 个 STS 文件", self.output_paths.len());
+        // 文件夹中同时有 xdts 和 tdts 时按格式分别列出
+        if self.xdts.files > 0 && self.tdts.files > 0 {
+            let tallies = [(SourceFormat::Xdts, &self.xdts), (SourceFormat::Tdts, &self.tdts)];
+            for (format, tally) in tallies {
+                println!(
+                    "  {}: {} 个源文件，生成 {} 个文件",
+                    format.name(),
+                    tally.files,
+                    tally.outputs
+                );
+            }
+        }
+        if self.skipped_current > 0 {
+            println!("跳过了 {} 个已是最新的 STS 文件", self.skipped_current);
+        }
+        if self.skipped_done > 0 {
+            println!("跳过了 {} 个状态文件中已完成的源文件", self.skipped_done);
+        }
+        let seconds = elapsed.as_secs_f64();
+        print!("用时 {:.2} 秒", seconds);
+        if self.total_files > 0 && seconds > 0.0 {
+            print!(
+                "，平均每个文件 {:.1} 毫秒 ({:.1} 个文件/秒，{:.2} MB/秒)",
+                seconds * 1000.0 / self.total_files as f64,
+                self.total_files as f64 / seconds,
+                self.input_bytes as f64 / (1024.0 * 1024.0) / seconds
+            );
+        }
+        println!();
+
+        if !self.output_paths.is_empty() {
+            println!("\n生成的文件:");
+            for path in self.output_paths.iter().take(10) {
+                let size = std::fs::metadata(path)?.len();
+                println!(
+                    "  - {} ({} 字节)",
+                    path.file_name().unwrap().to_string_lossy(),
+                    format_number(size)
+                );
+            }
+            if self.output_paths.len() > 10 {
+                println!("  ... 还有 {} 个文件", self.output_paths.len() - 10);
+            }
+        }
+
+        if show_warnings && !self.warnings.is_empty() {
+            let warning_count: usize = self.warnings.iter().map(|(_, w)| w.len()).sum();
+            println!("\n警告 {} 项:", warning_count);
+            for (source, warnings) in &self.warnings {
+                println!("  - {}", source.display());
+                for warning in warnings.iter() {
+                    println!("    {}", warning);
                 }
             }
         }
+
+        if !self.failures.is_empty() {
+            println!("\n失败 {} 项:", self.failures.len());
+            for failure in &self.failures {
+                println!("  - {}", failure.source.display());
+                if let Some(name) = &failure.timesheet {
+                    println!("    时间表: {}", name);
+                }
+                println!("    错误: {}", failure.message);
+            }
+        }
+
+        Ok(())
     }
+}
 
-    Ok(output_paths)
+/// 并行转换时每完成一个文件打印一行进度；`quiet` 时只打印失败
+fn print_progress(
+    finished: usize,
+    total: usize,
+    file_name: &str,
+    result: &Result<ProcessOutcome>,
+    quiet: bool,
+) {
+    match result {
+        Ok(_) if quiet => {}
+        Ok(outcome) if outcome.already_done => {
+            println!("[{}/{}] - {} (状态文件中已完成，跳过)", finished, total, file_name)
+        }
+        Ok(outcome) if outcome.skipped_current > 0 => println!(
+            "[{}/{}] ✓ {} ({} 个 STS 文件, {} 个已是最新)",
+            finished,
+            total,
+            file_name,
+            outcome.output_paths.len(),
+            outcome.skipped_current
+        ),
+        Ok(outcome) => println!(
+            "[{}/{}] ✓ {} ({} 个 STS 文件)",
+            finished,
+            total,
+            file_name,
+            outcome.output_paths.len()
+        ),
+        Err(e) => eprintln!("[{}/{}] ✗ {}: {}", finished, total, file_name, e),
+    }
+}
+
+/// 整次运行中各源文件共享的状态
+struct Batch {
+    /// `--state-file` 的记录
+    state: Option<state::StateFile>,
+    /// 已生成的输出路径，不同源文件的输出重名时改名
+    outputs: OutputPaths,
+}
+
+/// 处理一个源文件，并按 `--state-file` 跳过已完成的文件、记录新完成的文件
+fn process_file_resumable(
+    input_path: &Path,
+    output_dir: Option<&Path>,
+    quiet: bool,
+    confirm: bool,
+    opts: &Options,
+    batch: &Batch,
+    scan_root: Option<&Path>,
+) -> Result<ProcessOutcome> {
+    let confirm = confirm.then_some(confirm_message_box as pipeline::ConfirmPrompt);
+    // 扫描根目录到源文件所在目录的 .xdts2sts.toml 覆盖命令行选项
+    let dir_opts = config::dir_options(input_path, scan_root, opts)?;
+    let opts = dir_opts.as_ref().unwrap_or(opts);
+
+    let outputs = &batch.outputs;
+    let Some(state) = &batch.state else {
+        return process_file_with_timeout(input_path, output_dir, quiet, confirm, opts, outputs);
+    };
+    if !opts.force && state.is_done(input_path) {
+        return Ok(ProcessOutcome {
+            already_done: true,
+            ..Default::default()
+        });
+    }
+
+    let outcome = process_file_with_timeout(input_path, output_dir, quiet, confirm, opts, outputs)?;
+    // 只记录完整转换成功的文件；试运行和只校验时没有写入，不记录
+    if outcome.failures.is_empty() && !outcome.cancelled && !opts.dry_run && !opts.validate_only {
+        state.mark_done(input_path)?;
+    }
+    Ok(outcome)
 }
 
-fn find_timesheet_files(folder_path: &Path) -> Result<Vec<PathBuf>> {
+/// 查找文件夹中的待转换文件；指定 `limit` 时找到 `limit + 1` 个就停止扫描
+fn find_timesheet_files(
+    folder_path: &Path,
+    opts: &Options,
+    limit: Option<usize>,
+) -> Result<Vec<PathBuf>> {
     let mut files = Vec::new();
 
+    // 默认只扫描文件夹本身，`--recursive` 时包括所有子文件夹
+    let max_depth = if opts.recursive { usize::MAX } else { 1 };
     for entry in WalkDir::new(folder_path)
-        .max_depth(1)
+        .max_depth(max_depth)
         .into_iter()
         .filter_map(|e| e.ok())
     {
         let path = entry.path();
-        if path.is_file() {
-            if let Some(ext) = path.extension() {
-                let ext_lower = ext.to_string_lossy().to_lowercase();
-                if ext_lower == "xdts" || ext_lower == "tdts" {
-                    files.push(path.to_path_buf());
-                }
+        if path.is_file() && opts.accepts_file(path) {
+            files.push(path.to_path_buf());
+            if limit.is_some_and(|max| files.len() > max) {
+                break;
             }
         }
     }
@@ -387,6 +981,22 @@ fn find_timesheet_files(folder_path: &Path) -> Result<Vec<PathBuf>> {
     Ok(files)
 }
 
+/// 创建并删除一个临时文件，确认目录可写（目录不存在时先创建）
+fn check_writable(dir: &Path) -> Result<()> {
+    let hint = || {
+        format!(
+            "输出目录不可写: {}\n请用 --output-dir 指定其他输出目录",
+            dir.display()
+        )
+    };
+
+    std::fs::create_dir_all(dir).with_context(hint)?;
+    let probe = dir.join(format!(".xdts2sts-write-test-{}", std::process::id()));
+    std::fs::File::create(&probe).with_context(hint)?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
 fn get_exe_dir() -> Result<PathBuf> {
     let exe_path = env::current_exe().context("无法获取程序路径")?;
     exe_path
@@ -403,7 +1013,29 @@ fn print_usage() {
         2. 拖放文件夹到本程序\n\
            → 查找并转换文件夹内所有 xdts/tdts 文件\n\
            → 保存到 'converted_sts' 目录中\n\n\
+        3. 命令行运行 xdts2sts --help\n\
+           → 查看所有选项\n\n\
            ";
 
     show_message_box("使用说明", usage_msg, false);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_stops_after_limit_plus_one_file() {
+        let dir = env::temp_dir().join(format!("xdts2sts-max-files-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..5 {
+            std::fs::write(dir.join(format!("cut{}.xdts", i)), "").unwrap();
+        }
+
+        let opts = Options::default();
+        assert_eq!(find_timesheet_files(&dir, &opts, Some(2)).unwrap().len(), 3);
+        assert_eq!(find_timesheet_files(&dir, &opts, None).unwrap().len(), 5);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
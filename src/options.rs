@@ -0,0 +1,743 @@
+use crate::cli::Cli;
+use crate::types::SourceFormat;
+use anyhow::{Context, Result};
+use clap::Parser;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// 非整数 cel 值（如 `12.5`）的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FractionalPolicy {
+    /// 四舍五入
+    Round,
+    /// 向下取整
+    #[default]
+    Floor,
+    /// 视为错误
+    Reject,
+}
+
+impl FromStr for FractionalPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "round" => Ok(FractionalPolicy::Round),
+            "floor" => Ok(FractionalPolicy::Floor),
+            "reject" => Ok(FractionalPolicy::Reject),
+            _ => anyhow::bail!("无效的非整数 cel 处理方式: {} (可选 round|floor|reject)", s),
+        }
+    }
+}
+
+/// cel 编号压缩的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizeCells {
+    /// 每层单独编号
+    PerLayer,
+    /// 整个时间表统一编号
+    Global,
+}
+
+impl FromStr for NormalizeCells {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "per-layer" => Ok(NormalizeCells::PerLayer),
+            "global" => Ok(NormalizeCells::Global),
+            _ => anyhow::bail!("无效的 cel 编号压缩范围: {} (可选 per-layer|global)", s),
+        }
+    }
+}
+
+/// 层的数据跨度与时间表时长不一致时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerDuration {
+    /// 保持最后一个 cel 补齐到时间表时长，超出时长的部分裁掉
+    Pad,
+    /// 每层在自己的数据跨度之后留空，超出时长的部分裁掉
+    Clip,
+    /// 时间表扩展到所有层中最长的数据跨度
+    Max,
+}
+
+impl FromStr for LayerDuration {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pad" => Ok(LayerDuration::Pad),
+            "clip" => Ok(LayerDuration::Clip),
+            "max" => Ok(LayerDuration::Max),
+            _ => anyhow::bail!("无效的层时长处理方式: {} (可选 pad|clip|max)", s),
+        }
+    }
+}
+
+/// `--cell-offset` 使 cel 编号小于 1 时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnderflowPolicy {
+    /// 视为错误
+    #[default]
+    Error,
+    /// 写为空白 (0) 并警告
+    ClampToNull,
+}
+
+impl FromStr for UnderflowPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(UnderflowPolicy::Error),
+            "clamp-to-null" => Ok(UnderflowPolicy::ClampToNull),
+            _ => anyhow::bail!("无效的 cel 下溢处理方式: {} (可选 clamp-to-null|error)", s),
+        }
+    }
+}
+
+/// cel 编号超过 `--max-cell` 时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverCellPolicy {
+    /// 视为错误
+    #[default]
+    Error,
+    /// 截断为上限并警告
+    Clamp,
+}
+
+impl FromStr for OverCellPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(OverCellPolicy::Error),
+            "clamp" => Ok(OverCellPolicy::Clamp),
+            _ => anyhow::bail!("无效的超限 cel 处理方式: {} (可选 error|clamp)", s),
+        }
+    }
+}
+
+/// 文本导出中保持（与上一帧相同的非空白 cel）的写法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldStyle {
+    /// 每帧都写 cel 编号
+    Repeat,
+    /// 只在切换的帧写编号，保持的帧留空
+    Blank,
+    /// 保持的帧写延续符号 `|`
+    Bar,
+}
+
+impl FromStr for HoldStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "repeat" => Ok(HoldStyle::Repeat),
+            "blank" => Ok(HoldStyle::Blank),
+            "bar" => Ok(HoldStyle::Bar),
+            _ => anyhow::bail!("无效的保持写法: {} (可选 repeat|blank|bar)", s),
+        }
+    }
+}
+
+/// 帧重新计时的倍率，如 `2/1`（一拍一改为一拍二）或 `1/2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retime {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Retime {
+    /// 按倍率换算帧号，四舍五入
+    pub fn scale(self, frame: u32) -> u32 {
+        let numerator = u64::from(frame) * u64::from(self.numerator);
+        let denominator = u64::from(self.denominator);
+        let scaled = (numerator * 2 + denominator) / (denominator * 2);
+        scaled.min(u64::from(u32::MAX)) as u32
+    }
+}
+
+impl FromStr for Retime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (numerator, denominator) = s.split_once('/').unwrap_or((s, "1"));
+        let parse = |part: &str| part.trim().parse::<u32>().ok().filter(|&n| n > 0);
+        match (parse(numerator), parse(denominator)) {
+            (Some(numerator), Some(denominator)) => Ok(Retime {
+                numerator,
+                denominator,
+            }),
+            _ => anyhow::bail!("无效的重新计时倍率: {} (如 2/1、1/2、3)", s),
+        }
+    }
+}
+
+/// `--explain` 要追踪的单元格：层名称和帧号（从 1 开始）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplainTarget {
+    pub layer: String,
+    pub frame: u32,
+}
+
+impl FromStr for ExplainTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // 层名称中可能含有 ':'，以最后一个为分隔
+        let target = s.rsplit_once(':').and_then(|(layer, frame)| {
+            let frame = frame.trim().parse::<u32>().ok().filter(|&n| n > 0)?;
+            (!layer.is_empty()).then(|| ExplainTarget {
+                layer: layer.to_string(),
+                frame,
+            })
+        });
+        target.with_context(|| format!("无效的 --explain 值: {} (如 A:12，帧号从 1 开始)", s))
+    }
+}
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// ShiraheiTimeSheet 二进制
+    #[default]
+    Sts,
+    /// OpenToonz 可导入的曝光表 CSV
+    Otcsv,
+    /// 每帧每层一行的长格式 CSV
+    LongCsv,
+    /// 解析后的时间表（关键帧形式）
+    Json,
+}
+
+impl OutputFormat {
+    /// 全部输出格式
+    pub const ALL: [OutputFormat; 4] = [
+        OutputFormat::Sts,
+        OutputFormat::Otcsv,
+        OutputFormat::LongCsv,
+        OutputFormat::Json,
+    ];
+
+    /// `--format` 中使用的名称
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Sts => "sts",
+            OutputFormat::Otcsv => "otcsv",
+            OutputFormat::LongCsv => "long-csv",
+            OutputFormat::Json => "json",
+        }
+    }
+
+    /// 输出文件的扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Sts => "sts",
+            OutputFormat::Otcsv | OutputFormat::LongCsv => "csv",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // "csv" 为 OpenToonz 曝光表 CSV 的简写
+        if s == "csv" {
+            return Ok(OutputFormat::Otcsv);
+        }
+        OutputFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == s)
+            .with_context(|| format!("无效的输出格式: {} (可选 sts|otcsv|csv|long-csv|json)", s))
+    }
+}
+
+/// STS 帧数据区每个 cell 的字节宽度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CellWidth {
+    /// u8，cel 编号不超过 255
+    U8,
+    /// u16（标准 STS），cel 编号不超过 65535
+    #[default]
+    U16,
+    /// u32，用于 cel 编号超过 65535 的大型 cel 库
+    U32,
+}
+
+impl CellWidth {
+    /// 每个 cell 的字节数
+    pub fn bytes(self) -> usize {
+        match self {
+            CellWidth::U8 => 1,
+            CellWidth::U16 => 2,
+            CellWidth::U32 => 4,
+        }
+    }
+
+    /// 写入文件头偏移 21 字节的标志；标准宽度为 0，保持与原格式一致
+    pub fn header_flag(self) -> u8 {
+        match self {
+            CellWidth::U16 => 0,
+            other => other.bytes() as u8,
+        }
+    }
+
+    /// 由文件头的标志还原 cell 宽度；未知标志返回 None
+    pub fn from_header_flag(flag: u8) -> Option<Self> {
+        match flag {
+            0 => Some(CellWidth::U16),
+            1 => Some(CellWidth::U8),
+            4 => Some(CellWidth::U32),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for CellWidth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "1" => Ok(CellWidth::U8),
+            "2" => Ok(CellWidth::U16),
+            "4" => Ok(CellWidth::U32),
+            _ => anyhow::bail!("无效的 cell 宽度: {} (可选 1|2|4)", s),
+        }
+    }
+}
+
+/// 文件夹中待转换文件数的默认上限，超过时需确认
+pub const DEFAULT_MAX_FILES: usize = 5000;
+
+/// 输出文件写入缓冲区的默认大小 (1 MiB)
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+
+/// 默认的延长保持标记
+pub const DEFAULT_HOLD_MARKERS: &[&str] = &["E"];
+
+/// 转换选项
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// 裁剪末尾的空白帧
+    pub trim_trailing_blank: bool,
+    /// 裁剪后额外保留的空白帧数
+    pub trim_margin: u32,
+    /// 将同一源文件的所有时间表打包为一个 .stsx 容器
+    pub container: bool,
+    /// 仅当源文件比已有输出更新时才重新转换
+    pub overwrite_if_newer: bool,
+    /// 单文件模式下写入前显示预览并确认
+    pub confirm: bool,
+    /// 非整数 cel 值的处理方式
+    pub fractional: FractionalPolicy,
+    /// 按 fieldName 选择 cel 所在的 field
+    pub field_name: Option<String>,
+    /// 将 cel 编号压缩为从 1 开始的连续编号
+    pub normalize_cells: Option<NormalizeCells>,
+    /// 额外输出曝光预览 PNG 的目录
+    pub preview_png: Option<PathBuf>,
+    /// 源文件大小上限（字节），超过则跳过
+    pub max_file_size: Option<u64>,
+    /// cel 编号后的延长保持标记，未指定时使用 [`DEFAULT_HOLD_MARKERS`]
+    pub hold_markers: Option<Vec<String>>,
+    /// 额外接受的扩展名（如 `json`、`xdts.json`），按内容判断格式
+    pub also_ext: Vec<String>,
+    /// 反转输出的层顺序
+    pub reverse_layers: bool,
+    /// 帧率，用于换算时长（秒）
+    pub fps: Option<f64>,
+    /// 编码前将层名称做 Unicode NFC 规范化
+    pub normalize_names: bool,
+    /// 编码后打印输出数据的前 N 字节
+    pub dump_bytes: Option<usize>,
+    /// 层名称无法编码为 Shift-JIS 时报错而不是警告
+    pub strict_names: bool,
+    /// 单个文件的处理时间上限，超时视为失败
+    pub timeout: Option<Duration>,
+    /// 输出格式
+    pub format: OutputFormat,
+    /// 源数据中显式空白（`SYMBOL_NULL_CELL`）写入的 cel 值，默认为 0
+    pub null_cell_value: u32,
+    /// 输出 cel 编号的起始值（源数据视为从 0 开始），非空 cel 按差值偏移
+    pub cel_base: u32,
+    /// 非空 cel 额外加上的偏移量，可为负
+    pub cell_offset: i32,
+    /// 偏移后 cel 编号小于 1 时的处理方式
+    pub underflow: UnderflowPolicy,
+    /// 只解析并校验，不写入任何文件
+    pub validate_only: bool,
+    /// 多时间表的源文件输出到以源文件名命名的子目录
+    pub subdir_per_source: bool,
+    /// STS 帧数据区每个 cell 的字节宽度
+    pub cell_width: CellWidth,
+    /// 打印 `--format json` 输出的 JSON Schema 后退出
+    pub json_schema: bool,
+    /// 以 JSON 输出版本和支持的格式后退出
+    pub info: bool,
+    /// `--layer-order-from` 文件中列出的层顺序
+    pub layer_order: Option<Vec<String>>,
+    /// `--keep-layers` 文件中列出的层：只保留这些层，并按列出的顺序排列
+    pub keep_layers: Option<Vec<String>>,
+    /// 文本导出中记录源文件路径、时间表名称和转换时间
+    pub embed_provenance: bool,
+    /// 同一 cut 内时间表时长允许相差的帧数（`--validate-only` 时检查）
+    pub cut_duration_tolerance: u32,
+    /// 将同一 cut 内所有时间表的时长统一为其中的最大值
+    pub align_cut_durations: bool,
+    /// 与 `--format json` 同用：每个源文件只输出时间表概要，不含帧数据
+    pub metadata_only: bool,
+    /// 输出目录；未指定时单个文件输出到源文件同目录，文件夹输出到 exe 同目录的 converted_sts
+    pub output_dir: Option<PathBuf>,
+    /// 时间表名称使用不带扩展名的源文件名，输出文件名不再重复源文件名
+    pub strip_extension_in_name: bool,
+    /// 转换结束后在输出目录写入汇总的 index.csv
+    pub index_csv: bool,
+    /// 移除逐帧数据完全相同的重复层
+    pub dedupe_layers: bool,
+    /// 不输出警告，只保留进度和错误信息
+    pub quiet_warnings: bool,
+    /// 先写入临时文件再重命名，避免留下写了一半的 STS 文件
+    pub atomic: bool,
+    /// 文件夹中待转换文件数上限，未指定时使用 [`DEFAULT_MAX_FILES`]
+    pub max_files: Option<usize>,
+    /// 文件数超过上限时不再确认
+    pub yes: bool,
+    /// 输出的 cel 编号上限
+    pub max_cell: Option<u32>,
+    /// cel 编号超过上限时的处理方式
+    pub on_over_cell: OverCellPolicy,
+    /// 按倍率缩放关键帧位置和时长
+    pub retime: Option<Retime>,
+    /// 层名称写成固定字节宽度的字段（空格填充/截断），替代 `[长度][名称]` 布局
+    pub name_field_width: Option<u8>,
+    /// TDTS 按 cut 拆分，每个 cut 输出一个 XDTS 文件
+    pub split_cuts: bool,
+    /// 只编码并列出将要写入的文件，不写入
+    pub dry_run: bool,
+    /// 输出每个时间表的详细信息
+    pub verbose: bool,
+    /// 读取输入的 STS 文件并列出其中的层名称
+    pub names: bool,
+    /// 每写入一个文件后暂停的时间，默认不暂停
+    pub write_delay: Duration,
+    /// 只输出与该 STS 文件相比有变化或新增的层
+    pub diff_against: Option<PathBuf>,
+    /// 导出 XDTS 时写入的首行注释
+    pub xdts_comment: Option<String>,
+    /// 关键帧数超过帧数的这个比例时警告该层过密
+    pub dense_layer_threshold: Option<f64>,
+    /// `--format long-csv` 时省略 cel 为 0（空白）的行
+    pub skip_blank_rows: bool,
+    /// 并行转换使用的线程数，未指定时由 rayon 按 CPU 核数决定；`--verbose` 时固定为 1
+    pub threads: Option<usize>,
+    /// 写入每个 STS 后立即读回，与写入的数据逐帧比对
+    pub verify_after_write: bool,
+    /// 文本导出中保持的写法；未指定时 otcsv 留空，long-csv 重复编号
+    pub hold_style: Option<HoldStyle>,
+    /// 转换结束后把生成的文件打包到此 zip
+    pub zip_output: Option<PathBuf>,
+    /// 只解析名称匹配此正则的时间表
+    pub only_tables: Option<Regex>,
+    /// `--cel-equivalence` 文件中的等价 cel：编号 → 该组的规范编号
+    pub cel_equivalence: Option<BTreeMap<u32, u32>>,
+    /// 输出该单元格从源数据到展开结果的推导过程
+    pub explain: Option<ExplainTarget>,
+    /// 层的数据跨度与时间表时长不一致时的处理方式；未指定时按 pad 处理且不报告
+    pub per_layer_duration: Option<LayerDuration>,
+    /// 输出文件写入缓冲区的字节数，未指定时使用 [`DEFAULT_BUFFER_SIZE`]
+    pub buffer_size: Option<usize>,
+    /// 保留记号帧及每个关键帧的原始值（含记号），写入 JSON 导出
+    pub preserve_symbols: bool,
+    /// 任何会丢失源数据的情况都视为错误
+    pub strict: bool,
+    /// 列出输入文件中各时间表的 field 和 header 结构，不做转换
+    pub list_fields: bool,
+    /// `--list-fields` 以 JSON 输出
+    pub json: bool,
+    /// 没有名称的层的名称模板，`{index}` 替换为 track_no
+    pub empty_layer_name: Option<String>,
+    /// 断点续转的状态文件：记录已完成的源文件，再次运行时跳过
+    pub state_file: Option<PathBuf>,
+    /// 比较两个源文件（旧、新）中各时间表的曝光变化，不做转换
+    pub source_diff: Option<(PathBuf, PathBuf)>,
+    /// 文本导出中非零 cel 写为十六进制（如 `0x1F`），只影响显示，不影响 STS
+    pub cell_as_hex: bool,
+    /// 对目录中的每个源文件做 STS 编码/解码自检，不写入文件
+    pub round_trip_check: Option<PathBuf>,
+    /// 按层名称前缀（第一个分隔符之前的部分）拆分时间表，每组输出一个文件
+    pub split_by_prefix: bool,
+    /// `--split-by-prefix` 的分隔符（默认 `_`）
+    pub prefix_delimiter: Option<String>,
+    /// `--cut-map` 文件中的 cut 名称对照：原始 cut 代码 → 显示用名称
+    pub cut_map: Option<BTreeMap<String, String>>,
+    /// STS 末尾追加 CRC32 尾部，并在文件头的 cell 宽度标志中置位
+    pub footer_crc: bool,
+    /// 从标准输入读取一个源文件
+    pub from_stdin: bool,
+    /// `--from-stdin` 的源格式；不指定时按内容判断
+    pub input_format: Option<SourceFormat>,
+    /// 把转换结果写到标准输出而不是文件
+    pub stdout: bool,
+    /// 批处理时不逐个列出文件的处理过程，只打印失败和最后的总结
+    pub quiet: bool,
+    /// 忽略 `--state-file` 的跳过，全部重新转换；不能与 `--overwrite-if-newer` 同时使用
+    pub force: bool,
+    /// 转换文件夹时包括所有子文件夹，输出按子文件夹结构放置
+    pub recursive: bool,
+}
+
+impl Options {
+    /// 文件名是否带有可处理的扩展名
+    pub fn accepts_file(&self, path: &Path) -> bool {
+        let file_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_lowercase(),
+            None => return false,
+        };
+
+        ["xdts", "tdts"]
+            .iter()
+            .map(|ext| ext.to_string())
+            .chain(self.also_ext.iter().map(|ext| ext.to_lowercase()))
+            .any(|ext| file_name.ends_with(&format!(".{}", ext)))
+    }
+
+    /// 按 `--fps` 把帧数换算为秒；未指定帧率时返回 None
+    pub fn duration_seconds(&self, frame_count: usize) -> Option<f64> {
+        self.fps.map(|fps| frame_count as f64 / fps)
+    }
+
+    /// 生效的文件数上限
+    pub fn max_files(&self) -> usize {
+        self.max_files.unwrap_or(DEFAULT_MAX_FILES)
+    }
+
+    /// 文本导出中 cel 编号的写法：`--cell-as-hex` 时非零值为 "0x1F"，否则为十进制
+    pub fn format_cell(&self, cell: u32) -> String {
+        if self.cell_as_hex && cell != 0 {
+            format!("0x{:X}", cell)
+        } else {
+            cell.to_string()
+        }
+    }
+
+    /// 生效的写入缓冲区大小
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE)
+    }
+
+    /// 生效的延长保持标记列表
+    pub fn hold_markers(&self) -> impl Iterator<Item = &str> {
+        let (custom, default): (&[String], &[&str]) = match &self.hold_markers {
+            Some(markers) => (markers, &[]),
+            None => (&[], DEFAULT_HOLD_MARKERS),
+        };
+        custom
+            .iter()
+            .map(String::as_str)
+            .chain(default.iter().copied())
+    }
+
+    /// 解析命令行参数，返回选项和输入路径
+    pub fn parse_args(args: &[String]) -> Result<(Options, Vec<String>)> {
+        let mut opts = Options::default();
+        let inputs = opts.apply_args(args)?;
+        Ok((opts, inputs))
+    }
+
+    /// 在当前选项的基础上再应用一组选项（如 `.xdts2sts.toml` 转换来的参数），后出现的值覆盖前面的值
+    pub fn with_overrides(&self, args: &[String]) -> Result<Options> {
+        let mut opts = self.clone();
+        let inputs = opts.apply_args(args)?;
+        if let Some(input) = inputs.first() {
+            anyhow::bail!("不是选项: {}", input);
+        }
+        Ok(opts)
+    }
+
+    /// 解析命令行参数写入当前选项，返回非选项参数（输入路径）
+    fn apply_args(&mut self, args: &[String]) -> Result<Vec<String>> {
+        let args = std::iter::once("xdts2sts").chain(args.iter().map(String::as_str));
+        let inputs = Cli::try_parse_from(args)?.apply(self)?;
+        self.validate()?;
+        Ok(inputs)
+    }
+
+    /// 检查选项组合是否有效
+    fn validate(&self) -> Result<()> {
+        let opts = self;
+        if opts.container && opts.format != OutputFormat::Sts {
+            anyhow::bail!("--container 只能用于 sts 输出格式");
+        }
+
+        if opts.split_cuts && (opts.container || opts.metadata_only) {
+            anyhow::bail!("--split-cuts 不能与 --container 或 --metadata-only 同时使用");
+        }
+
+        if opts.dry_run && (opts.split_cuts || opts.metadata_only) {
+            anyhow::bail!("--dry-run 不能与 --split-cuts 或 --metadata-only 同时使用");
+        }
+
+        if opts.json && !opts.list_fields {
+            anyhow::bail!("--json 需要与 --list-fields 同时使用");
+        }
+
+        if opts.force && opts.overwrite_if_newer {
+            anyhow::bail!("--force 不能与 --overwrite-if-newer 同时使用");
+        }
+
+        if opts.from_stdin != opts.stdout {
+            anyhow::bail!("--from-stdin 和 --stdout 需要同时使用");
+        }
+
+        if opts.input_format.is_some() && !opts.from_stdin {
+            anyhow::bail!("--input-format 需要与 --from-stdin 同时使用");
+        }
+
+        if opts.stdout && (opts.format == OutputFormat::Sts || opts.container || opts.split_cuts) {
+            anyhow::bail!("--stdout 只能用于文本输出格式 (otcsv|long-csv|json)");
+        }
+
+        if opts.prefix_delimiter.is_some() && !opts.split_by_prefix {
+            anyhow::bail!("--prefix-delimiter 需要与 --split-by-prefix 同时使用");
+        }
+
+        if opts.metadata_only && opts.format != OutputFormat::Json {
+            anyhow::bail!("--metadata-only 需要与 --format json 同时使用");
+        }
+
+        Ok(())
+    }
+}
+
+/// 读取每行一个名称的列表文件，忽略空行和首尾空白
+pub(crate) fn read_name_list(path: &str, kind: &str) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("无法读取{}文件: {}", kind, path))?;
+    Ok(content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// 解析 cel 等价文件：每行一组编号（空格或逗号分隔），第一个为规范编号；
+/// 空行和 `#` 开头的行忽略
+pub(crate) fn parse_cel_equivalence(content: &str) -> Result<BTreeMap<u32, u32>> {
+    let mut map = BTreeMap::new();
+    let mut seen = BTreeSet::new();
+
+    for (line_no, line) in content.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let cels = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse::<u32>()
+                    .ok()
+                    .filter(|&cel| cel != 0)
+                    .with_context(|| {
+                        format!("cel 等价文件第 {} 行: 无效的 cel 编号 {}", line_no + 1, item)
+                    })
+            })
+            .collect::<Result<Vec<u32>>>()?;
+
+        for &cel in &cels {
+            if !seen.insert(cel) {
+                anyhow::bail!("cel 等价文件第 {} 行: cel {} 出现在多个组中", line_no + 1, cel);
+            }
+        }
+        if let Some((&canonical, others)) = cels.split_first() {
+            map.extend(others.iter().map(|&cel| (cel, canonical)));
+        }
+    }
+
+    Ok(map)
+}
+
+/// 解析 cut 对照 CSV：每行 `原始 cut,名称`，字段两端的空白和双引号去掉；
+/// 空行和 `#` 开头的行忽略
+pub(crate) fn parse_cut_map(content: &str) -> Result<BTreeMap<String, String>> {
+    let mut map = BTreeMap::new();
+
+    for (line_no, line) in content.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let field = |s: &str| s.trim().trim_matches('"').to_string();
+        let (cut, name) = line
+            .split_once(',')
+            .map(|(cut, name)| (field(cut), field(name)))
+            .filter(|(cut, name)| !cut.is_empty() && !name.is_empty())
+            .with_context(|| format!("cut 对照文件第 {} 行: 应为 \"原始 cut,名称\"", line_no + 1))?;
+        if map.insert(cut.clone(), name).is_some() {
+            anyhow::bail!("cut 对照文件第 {} 行: cut '{}' 重复", line_no + 1, cut);
+        }
+    }
+
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_separates_inputs() {
+        let (opts, inputs) =
+            Options::parse_args(&args(&["a.xdts", "--cell-offset", "-1", "--jobs", "2", "b"]))
+                .unwrap();
+        assert_eq!(inputs, ["a.xdts", "b"]);
+        assert_eq!(opts.cell_offset, -1);
+        assert_eq!(opts.threads, Some(2));
+    }
+
+    #[test]
+    fn force_conflicts_with_overwrite_if_newer() {
+        let err = Options::parse_args(&args(&["--force", "--overwrite-if-newer"])).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+    }
+
+    #[test]
+    fn overrides_keep_unset_options() {
+        let (opts, _) = Options::parse_args(&args(&["--strict", "--also-ext", "json"])).unwrap();
+        let overridden = opts
+            .with_overrides(&args(&["--format", "otcsv", "--strict", "--also-ext", ".xdt"]))
+            .unwrap();
+        assert!(overridden.strict);
+        assert_eq!(overridden.format, OutputFormat::Otcsv);
+        assert_eq!(overridden.also_ext, ["json", "xdt"]);
+
+        assert!(opts.with_overrides(&args(&["a.xdts"])).is_err());
+    }
+
+    #[test]
+    fn switch_accepts_explicit_value() {
+        let (opts, inputs) = Options::parse_args(&args(&["--strict", "a.xdts", "--quiet=true"])).unwrap();
+        assert_eq!(inputs, ["a.xdts"]);
+        assert!(opts.strict && opts.quiet);
+
+        let overridden = opts.with_overrides(&args(&["--strict=false"])).unwrap();
+        assert!(!overridden.strict);
+        assert!(overridden.quiet);
+    }
+}
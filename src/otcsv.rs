@@ -0,0 +1,100 @@
+//! OpenToonz 曝光表 CSV 导出
+//!
+//! 布局:
+//!
+//! ```text
+//! Frame,<层名称1>,<层名称2>,...
+//! 1,1,1
+//! 2,,2        ← 空单元格表示保持上一帧（`--hold-style` 可改为重复编号或 `|`）
+//! 3,X,        ← X 表示从此帧起为空白
+//! ```
+
+use crate::converter::{expand_timesheet, ConvertReport};
+use crate::options::{HoldStyle, Options};
+use crate::provenance::Provenance;
+use crate::types::Timesheet;
+use crate::warning::Warnings;
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::path::Path;
+
+/// 保持上一帧时写入的内容
+const HOLD: &str = "";
+/// `--hold-style bar` 时保持的帧写入的延续符号
+pub const HOLD_BAR: &str = "|";
+/// 切换为空白时写入的内容
+const BLANK: &str = "X";
+
+pub fn save_otcsv(
+    timesheet: &Timesheet,
+    output_path: &Path,
+    verbose: bool,
+    opts: &Options,
+    provenance: Option<&Provenance>,
+    warnings: &mut Warnings,
+) -> Result<ConvertReport> {
+    let (mut text, report) = encode_otcsv(timesheet, verbose, opts, warnings)?;
+
+    if let Some(provenance) = provenance {
+        text.insert_str(0, &format!("{}\n", provenance.comment_line()));
+    }
+
+    std::fs::write(output_path, text)
+        .with_context(|| format!("写入文件失败: {}", output_path.display()))?;
+
+    if verbose {
+        println!("\n文件已生成: {}", output_path.display());
+    }
+
+    Ok(report)
+}
+
+/// 将时间表编码为 OpenToonz 可导入的 CSV 文本
+pub fn encode_otcsv(
+    timesheet: &Timesheet,
+    verbose: bool,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<(String, ConvertReport)> {
+    let (all_layers_cells, report) = expand_timesheet(timesheet, verbose, opts, warnings)?;
+
+    let mut text = String::from("Frame");
+    for layer in &timesheet.layers {
+        text.push(',');
+        text.push_str(&csv_field(&layer.name));
+    }
+    text.push('\n');
+
+    let hold_style = opts.hold_style.unwrap_or(HoldStyle::Blank);
+    for frame in 0..report.frame_count {
+        text.push_str(&(frame + 1).to_string());
+        for cells in &all_layers_cells {
+            text.push(',');
+            let cell = cells[frame];
+            let previous = if frame == 0 { 0 } else { cells[frame - 1] };
+            if cell == previous {
+                match hold_style {
+                    HoldStyle::Repeat if cell != 0 => text.push_str(&opts.format_cell(cell)),
+                    HoldStyle::Bar if cell != 0 => text.push_str(HOLD_BAR),
+                    _ => text.push_str(HOLD),
+                }
+            } else if cell == 0 {
+                text.push_str(BLANK);
+            } else {
+                text.push_str(&opts.format_cell(cell));
+            }
+        }
+        text.push('\n');
+    }
+
+    Ok((text, report))
+}
+
+/// 含逗号、引号或换行的字段用双引号包围
+pub fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
@@ -1,61 +1,248 @@
+use crate::options::{FractionalPolicy, Options, OutputFormat};
 use crate::types::*;
+use crate::warning::{Warning, Warnings};
 use anyhow::{Context, Result};
+use serde::Serialize;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::Read;
 use std::path::Path;
 
-pub fn load_timesheets(path: &Path) -> Result<Vec<Timesheet>> {
+/// 读取源文件中的所有时间表，同时返回识别出的源格式
+pub fn load_timesheets(
+    path: &Path,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<(SourceFormat, Vec<Timesheet>)> {
+    let (format, comment, json_str) = read_source(path, opts)?;
+    let timesheets = load_source(path, format, comment, &json_str, opts, warnings)?;
+    Ok((format, timesheets))
+}
+
+/// 从标准输入读取一个源文件（`--from-stdin`）；时间表名称中的源文件部分为 "stdin"
+pub fn load_timesheets_from_stdin(
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<(SourceFormat, Vec<Timesheet>)> {
+    let mut content = String::new();
+    let limit = opts.max_file_size.map_or(u64::MAX, |limit| limit.saturating_add(1));
+    std::io::stdin()
+        .take(limit)
+        .read_to_string(&mut content)
+        .context("读取标准输入失败")?;
+    if let Some(limit) = opts.max_file_size {
+        if content.len() as u64 > limit {
+            anyhow::bail!("标准输入过大: 超过 {} 字节", limit);
+        }
+    }
+
+    let (comment, json_str) = split_comment_line(&content);
+    let format = match opts.input_format {
+        Some(format) => format,
+        None => detect_format(json_str)
+            .context("无法识别标准输入的格式，请用 --input-format xdts|tdts 指定")?,
+    };
+    let path = Path::new("stdin");
+    let timesheets =
+        load_source(path, format, comment.map(str::to_string), json_str, opts, warnings)?;
+    Ok((format, timesheets))
+}
+
+/// 按格式解析已读入的 JSON 文本
+fn load_source(
+    path: &Path,
+    format: SourceFormat,
+    comment: Option<String>,
+    json_str: &str,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Vec<Timesheet>> {
+    let timesheets = match format {
+        SourceFormat::Xdts => {
+            let mut timesheets = load_xdts(path, json_str, opts, warnings)?;
+            // 保留首行注释，重新导出 XDTS 时原样写回
+            for ts in &mut timesheets {
+                ts.comment = comment.clone();
+            }
+            timesheets
+        }
+        SourceFormat::Tdts => load_tdts(path, json_str, opts, warnings)?,
+    };
+    Ok(timesheets)
+}
+
+/// 读取源文件并识别格式，返回 (格式, 首行注释, JSON 文本)
+fn read_source(path: &Path, opts: &Options) -> Result<(SourceFormat, Option<String>, String)> {
     let ext = path
         .extension()
         .and_then(|s| s.to_str())
         .map(|s| s.to_lowercase())
-        .context("无法获取文件扩展名")?;
+        .unwrap_or_default();
+
+    let (comment, json_str) = read_json_file(path, opts.max_file_size)?;
+
+    // 非标准扩展名按内容判断格式
+    let format = match ext.as_str() {
+        "xdts" => SourceFormat::Xdts,
+        "tdts" => SourceFormat::Tdts,
+        _ => detect_format(&json_str)
+            .with_context(|| format!("无法识别文件格式: {}", path.display()))?,
+    };
+
+    Ok((format, comment, json_str))
+}
+
+/// `--list-fields` 中一个时间表的 field 结构
+#[derive(Debug, Serialize)]
+pub struct TableFields {
+    /// 时间表名称，TDTS 为 "cut->时间表名"
+    pub table: String,
+    /// `fields` 中的各 field
+    pub fields: Vec<FieldSummary>,
+    /// `timeTableHeaders` 中的各 header
+    pub headers: Vec<HeaderSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldSummary {
+    pub field_id: Option<u32>,
+    pub field_name: Option<String>,
+    pub tracks: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeaderSummary {
+    pub field_id: Option<u32>,
+    pub field_name: Option<String>,
+    pub names: usize,
+}
+
+/// 列出源文件中每个时间表的 field 和 header 结构，不做转换
+pub fn list_fields(path: &Path, opts: &Options) -> Result<(SourceFormat, Vec<TableFields>)> {
+    let (format, _, json_str) = read_source(path, opts)?;
+
+    let tables: Vec<(String, TimeTable)> = match format {
+        SourceFormat::Xdts => {
+            let root: XDTSRoot = serde_json::from_str(&json_str).context("解析 XDTS JSON 失败")?;
+            root.time_tables
+                .into_iter()
+                .map(|time_table| (time_table.name.clone(), time_table))
+                .collect()
+        }
+        SourceFormat::Tdts => {
+            let root: TDTSRoot = serde_json::from_str(&json_str).context("解析 TDTS JSON 失败")?;
+            root.time_sheets
+                .into_iter()
+                .flat_map(|time_sheet| {
+                    let cut = time_sheet.header.cut;
+                    time_sheet.time_tables.into_iter().map(move |time_table| {
+                        (format!("{}->{}", cut, time_table.name), time_table)
+                    })
+                })
+                .collect()
+        }
+    };
 
-    match ext.as_str() {
-        "xdts" => load_xdts(path),
-        "tdts" => load_tdts(path),
-        _ => anyhow::bail!("不支持的文件格式: {}", ext),
+    let tables = tables
+        .into_iter()
+        .map(|(table, time_table)| TableFields {
+            table,
+            fields: time_table
+                .fields
+                .iter()
+                .map(|field| FieldSummary {
+                    field_id: field.field_id,
+                    field_name: field.field_name.clone(),
+                    tracks: field.tracks.len(),
+                })
+                .collect(),
+            headers: time_table
+                .time_table_headers
+                .iter()
+                .map(|header| HeaderSummary {
+                    field_id: header.field_id,
+                    field_name: header.field_name.clone(),
+                    names: header.names.len(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok((format, tables))
+}
+
+/// 根据 JSON 根对象的键判断是 XDTS 还是 TDTS
+pub fn detect_format(json_str: &str) -> Option<SourceFormat> {
+    #[derive(serde::Deserialize)]
+    struct Probe {
+        #[serde(rename = "timeSheets")]
+        time_sheets: Option<serde::de::IgnoredAny>,
+        #[serde(rename = "timeTables")]
+        time_tables: Option<serde::de::IgnoredAny>,
+    }
+
+    let probe: Probe = serde_json::from_str(json_str).ok()?;
+    if probe.time_sheets.is_some() {
+        Some(SourceFormat::Tdts)
+    } else if probe.time_tables.is_some() {
+        Some(SourceFormat::Xdts)
+    } else {
+        None
     }
 }
 
-fn load_xdts(path: &Path) -> Result<Vec<Timesheet>> {
-    let json_str = read_json_file(path)?;
-    let root: XDTSRoot = serde_json::from_str(&json_str)
+pub(crate) fn load_xdts(
+    path: &Path,
+    json_str: &str,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Vec<Timesheet>> {
+    let root: XDTSRoot = serde_json::from_str(json_str)
         .context("解析 XDTS JSON 失败")?;
 
-    let filename = path
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown");
+    let filename = source_label(path, opts);
+
+    let cell_field_id = cell_field_id(SourceFormat::Xdts, root.version, warnings);
+
+    let mut time_tables = root.time_tables;
+    filter_tables(&mut time_tables, opts, warnings);
 
     let mut timesheets = Vec::new();
-    for time_table in root.time_tables {
+    for time_table in time_tables {
         let name = format!("{}->{}", filename, time_table.name);
-        let timesheet = parse_xdts_timetable(name, time_table)?;
+        let mut timesheet = parse_xdts_timetable(name, time_table, cell_field_id, opts, warnings)?;
+        timesheet.version = root.version;
         timesheets.push(timesheet);
     }
 
     Ok(timesheets)
 }
 
-fn load_tdts(path: &Path) -> Result<Vec<Timesheet>> {
-    let json_str = read_json_file(path)?;
-    let root: TDTSRoot = serde_json::from_str(&json_str)
+fn load_tdts(
+    path: &Path,
+    json_str: &str,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Vec<Timesheet>> {
+    let root: TDTSRoot = serde_json::from_str(json_str)
         .context("解析 TDTS JSON 失败")?;
 
-    let filename = path
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown");
+    let filename = source_label(path, opts);
+
+    let cell_field_id = cell_field_id(SourceFormat::Tdts, root.version, warnings);
 
     let mut timesheets = Vec::new();
-    for time_sheet in root.time_sheets {
-        let cut_name = &time_sheet.header.cut;
+    for mut time_sheet in root.time_sheets {
+        let cut_name = cut_display_name(&time_sheet.header.cut, opts, warnings);
+        filter_tables(&mut time_sheet.time_tables, opts, warnings);
+        check_cut_durations(&cut_name, &mut time_sheet.time_tables, opts, warnings);
         for time_table in time_sheet.time_tables {
             if !time_table.fields.is_empty() {
                 let name = format!("{}->{}->{}",
                     filename, cut_name, time_table.name);
-                let timesheet = parse_tdts_timetable(name, time_table)?;
+                let mut timesheet =
+                    parse_tdts_timetable(name, time_table, cell_field_id, opts, warnings)?;
+                timesheet.version = root.version;
+                timesheet.cut = Some(cut_name.clone());
                 timesheets.push(timesheet);
             }
         }
@@ -64,21 +251,161 @@ fn load_tdts(path: &Path) -> Result<Vec<Timesheet>> {
     Ok(timesheets)
 }
 
-fn read_json_file(path: &Path) -> Result<String> {
-    let file = File::open(path)
+/// 按 `--cut-map` 把原始 cut 代码换成对照表中的名称；对照表中没有时保留原始代码并报告
+fn cut_display_name(cut: &str, opts: &Options, warnings: &mut Warnings) -> String {
+    let Some(cut_map) = &opts.cut_map else {
+        return cut.to_string();
+    };
+    match cut_map.get(cut) {
+        Some(name) => name.clone(),
+        None => {
+            warnings.push(Warning::CutUnmapped {
+                cut: cut.to_string(),
+            });
+            cut.to_string()
+        }
+    }
+}
+
+/// 按 `--only-tables` 在解析前去掉名称不匹配的时间表，并报告跳过的数量
+fn filter_tables(time_tables: &mut Vec<TimeTable>, opts: &Options, warnings: &mut Warnings) {
+    let Some(pattern) = &opts.only_tables else {
+        return;
+    };
+    let before = time_tables.len();
+    time_tables.retain(|time_table| pattern.is_match(&time_table.name));
+    let skipped = before - time_tables.len();
+    if skipped > 0 {
+        warnings.push(Warning::TablesFiltered {
+            pattern: pattern.as_str().to_string(),
+            skipped,
+        });
+    }
+}
+
+/// 时间表名称中的源文件部分：默认为完整文件名，`--strip-extension-in-name` 时去掉扩展名
+fn source_label<'a>(path: &'a Path, opts: &Options) -> &'a str {
+    let name = if opts.strip_extension_in_name {
+        path.file_stem()
+    } else {
+        path.file_name()
+    };
+    name.and_then(|s| s.to_str()).unwrap_or("unknown")
+}
+
+/// 检查同一 cut 内各时间表的时长是否一致：
+/// `--validate-only` 时报告相差超过容差的 cut，`--align-cut-durations` 时统一为最大时长
+fn check_cut_durations(
+    cut_name: &str,
+    time_tables: &mut [TimeTable],
+    opts: &Options,
+    warnings: &mut Warnings,
+) {
+    let durations = time_tables
+        .iter()
+        .filter(|t| !t.fields.is_empty())
+        .map(|t| t.duration);
+    let (Some(min), Some(max)) = (durations.clone().min(), durations.max()) else {
+        return;
+    };
+
+    if opts.validate_only && max - min > opts.cut_duration_tolerance {
+        warnings.push(Warning::CutDurationMismatch {
+            cut: cut_name.to_string(),
+            durations: time_tables
+                .iter()
+                .filter(|t| !t.fields.is_empty())
+                .map(|t| (t.name.clone(), t.duration))
+                .collect(),
+        });
+    }
+
+    if opts.align_cut_durations {
+        for time_table in time_tables.iter_mut() {
+            time_table.duration = max;
+        }
+    }
+}
+
+/// 已知的格式版本
+const KNOWN_VERSIONS: &[u32] = &[5];
+
+/// 按格式版本确定 cel 所在 field 的 fieldId。
+/// 已知版本直接使用规范中的 fieldId；未知或缺少版本时沿用原有的推测方式
+/// （XDTS 取第一个 field，TDTS 取 fieldId 4），未知版本会发出警告
+fn cell_field_id(
+    format: SourceFormat,
+    version: Option<u32>,
+    warnings: &mut Warnings,
+) -> Option<u32> {
+    match version {
+        Some(version) if KNOWN_VERSIONS.contains(&version) => match format {
+            SourceFormat::Xdts => Some(0),
+            SourceFormat::Tdts => Some(4),
+        },
+        Some(version) => {
+            warnings.push(Warning::UnknownVersion { format, version });
+            default_cell_field_id(format)
+        }
+        None => default_cell_field_id(format),
+    }
+}
+
+fn default_cell_field_id(format: SourceFormat) -> Option<u32> {
+    match format {
+        SourceFormat::Xdts => None,
+        SourceFormat::Tdts => Some(4),
+    }
+}
+
+/// 读取文件，返回开头的注释行（如有）和其后的 JSON 文本
+fn read_json_file(path: &Path, max_file_size: Option<u64>) -> Result<(Option<String>, String)> {
+    let mut file = File::open(path)
         .with_context(|| format!("无法打开文件: {}", path.display()))?;
-    let reader = BufReader::new(file);
 
-    let lines: Vec<String> = reader
-        .lines()
-        .skip(1) // 跳过第一行注释
-        .collect::<Result<_, _>>()
+    // 读入内存前先检查文件大小
+    if let Some(limit) = max_file_size {
+        let size = file.metadata().context("读取文件信息失败")?.len();
+        if size > limit {
+            anyhow::bail!("文件过大，已跳过: {} 字节 (上限 {} 字节)", size, limit);
+        }
+    }
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)
         .context("读取文件失败")?;
 
-    Ok(lines.join("\n"))
+    let (comment, json) = split_comment_line(&content);
+    Ok((comment.map(str::to_string), json.to_string()))
+}
+
+/// 去掉 BOM，分离开头的注释行（如 "exchangeDigitalTimeSheet Save Data"）；
+/// 第一个非空白字符已经是 `{` 或 `[` 时没有注释行。
+/// 美化格式的 JSON 从下一行开始；压缩格式可能紧跟在注释后的同一行，
+/// 此时注释到第一个 `{` 为止（`//` 或 `#` 开头的整行注释除外）
+fn split_comment_line(content: &str) -> (Option<&str>, &str) {
+    let content = content.trim_start_matches('\u{feff}').trim_start();
+    if content.starts_with(['{', '[']) {
+        return (None, content);
+    }
+
+    let line_end = content.find('\n').unwrap_or(content.len());
+    let whole_line = content.starts_with("//") || content.starts_with('#');
+    let end = match content[..line_end].find('{') {
+        Some(brace) if !whole_line => brace,
+        _ => line_end,
+    };
+    let (comment, json) = content.split_at(end);
+    (Some(comment.trim_end()), json)
 }
 
-fn parse_xdts_timetable(name: String, time_table: TimeTable) -> Result<Timesheet> {
+fn parse_xdts_timetable(
+    name: String,
+    time_table: TimeTable,
+    cell_field_id: Option<u32>,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Timesheet> {
     let frame_count = time_table.duration;
 
     if time_table.fields.is_empty() {
@@ -86,46 +413,93 @@ fn parse_xdts_timetable(name: String, time_table: TimeTable) -> Result<Timesheet
             name,
             frame_count,
             layers: Vec::new(),
+            version: None,
+            cut: None,
+            comment: None,
         });
     }
 
-    let field = &time_table.fields[0];
-    let field_id = field.field_id;
+    let field = find_named_field(&time_table, opts, warnings)
+        .or_else(|| {
+            cell_field_id.and_then(|id| time_table.fields.iter().find(|f| f.field_id == Some(id)))
+        })
+        .unwrap_or(&time_table.fields[0]);
+
+    if opts.strict {
+        check_ignored_fields(&name, &time_table, Some(field), warnings);
+    }
 
     // 查找对应的名称列表
-    let names = time_table
-        .time_table_headers
-        .iter()
-        .find(|h| h.field_id == field_id)
-        .map(|h| &h.names);
+    let names = find_header_names(&time_table, field);
 
     let mut layers = Vec::new();
 
-    if let Some(names) = names {
-        for track in &field.tracks {
-            let layer_name = names
-                .get(track.track_no)
-                .cloned()
-                .unwrap_or_else(|| format!("Layer {}", track.track_no));
+    if names.is_some() || has_track_labels(field) {
+        let names = names.map_or(&[][..], Vec::as_slice);
+        for track in ordered_tracks(&time_table, field, names, warnings) {
+            let layer_name = track_layer_name(names, track, opts);
 
             let mut frames = Vec::new();
-            for frame_data in &track.frames {
-                if let Some(value) = frame_data.data.get(0).and_then(|d| d.values.get(0)) {
-                    let cell = parse_xdts_cell_value(value);
+            let mut skipped = Vec::new();
+            let mut held = Vec::new();
+            for frame_data in sorted_frames(&track.frames) {
+                if let Some(value) = frame_data.data.first().and_then(|d| d.values.first()) {
+                    // JSON 导出用 --preserve-symbols 时原始值（含标记）会记录在 source 中
+                    let recorded = opts.preserve_symbols && opts.format == OutputFormat::Json;
+                    if !recorded && strip_hold_marker(value, opts) != value {
+                        held.push(value.clone());
+                    }
+                    let cell = parse_xdts_cell_value(value, opts, warnings)?;
                     if let Some(cell) = cell {
                         frames.push(Frame {
                             frame: frame_data.frame,
                             cell,
+                            explicit_null: value == NULL_CELL,
+                            data: extra_data(frame_data),
+                            source: source_token(value, opts),
                         });
+                    } else {
+                        // 只有 JSON 导出能通过 --preserve-symbols 保留记号帧
+                        if !(opts.preserve_symbols && opts.format == OutputFormat::Json) {
+                            skipped.push(value.clone());
+                        }
+                        if opts.preserve_symbols {
+                            // 记号帧不改变 cel，保留为延续上一关键帧的帧以记录原始值
+                            let (cell, explicit_null) = frames
+                                .last()
+                                .map_or((0, false), |f: &Frame| (f.cell, f.explicit_null));
+                            frames.push(Frame {
+                                frame: frame_data.frame,
+                                cell,
+                                explicit_null,
+                                data: extra_data(frame_data),
+                                source: Some(value.clone()),
+                            });
+                        }
                     }
                 }
             }
+            if opts.strict && !skipped.is_empty() {
+                warnings.push(Warning::FramesSkipped {
+                    table: name.clone(),
+                    layer: layer_name.clone(),
+                    tokens: skipped,
+                });
+            }
+            if opts.strict && !held.is_empty() {
+                warnings.push(Warning::HoldMarkersDropped {
+                    table: name.clone(),
+                    layer: layer_name.clone(),
+                    tokens: held,
+                });
+            }
 
             // 优化关键帧
-            optimize_frames(&mut frames);
+            optimize_frames(&mut frames, opts);
 
             layers.push(Layer {
                 name: layer_name,
+                track_no: track.track_no,
                 frames,
             });
         }
@@ -135,48 +509,86 @@ fn parse_xdts_timetable(name: String, time_table: TimeTable) -> Result<Timesheet
         name,
         frame_count,
         layers,
+        version: None,
+        cut: None,
+        comment: None,
     })
 }
 
-fn parse_tdts_timetable(name: String, time_table: TimeTable) -> Result<Timesheet> {
+fn parse_tdts_timetable(
+    name: String,
+    time_table: TimeTable,
+    cell_field_id: Option<u32>,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Timesheet> {
     let frame_count = time_table.duration;
 
-    // 查找 fieldId = 4 的 field
-    let field = time_table
-        .fields
-        .iter()
-        .find(|f| f.field_id == 4);
+    // 查找 cel 所在的 field（通常为 fieldId = 4）
+    let field = find_named_field(&time_table, opts, warnings).or_else(|| {
+        time_table
+            .fields
+            .iter()
+            .find(|f| cell_field_id.is_some() && f.field_id == cell_field_id)
+    });
+
+    if opts.strict {
+        check_ignored_fields(&name, &time_table, field, warnings);
+    }
 
     // 查找对应的名称列表
-    let names = time_table
-        .time_table_headers
-        .iter()
-        .find(|h| h.field_id == 4)
-        .map(|h| &h.names);
+    let names = field.and_then(|field| find_header_names(&time_table, field));
 
     let mut layers = Vec::new();
 
-    if let (Some(field), Some(names)) = (field, names) {
-        for track in &field.tracks {
-            let layer_name = names
-                .get(track.track_no)
-                .cloned()
-                .unwrap_or_else(|| format!("Layer {}", track.track_no));
+    if let Some(field) = field.filter(|field| names.is_some() || has_track_labels(field)) {
+        let names = names.map_or(&[][..], Vec::as_slice);
+        for track in ordered_tracks(&time_table, field, names, warnings) {
+            let layer_name = track_layer_name(names, track, opts);
 
             let mut frames = Vec::new();
-            for frame_data in &track.frames {
-                if let Some(value) = frame_data.data.get(0).and_then(|d| d.values.get(0)) {
-                    let cell = parse_tdts_cell_value(value);
+            let mut skipped = Vec::new();
+            let mut held = Vec::new();
+            for frame_data in sorted_frames(&track.frames) {
+                if let Some(value) = frame_data.data.first().and_then(|d| d.values.first()) {
+                    // JSON 导出用 --preserve-symbols 时原始值（含标记）会记录在 source 中
+                    let recorded = opts.preserve_symbols && opts.format == OutputFormat::Json;
+                    if !recorded && strip_hold_marker(value, opts) != value {
+                        held.push(value.clone());
+                    }
+                    let cell = parse_tdts_cell_value(value, opts, warnings)?;
+                    // 不含数字的值（记号等）被当作空白
+                    if cell == 0 && value != NULL_CELL && !value.contains(|c: char| c.is_ascii_digit()) {
+                        skipped.push(value.clone());
+                    }
                     frames.push(Frame {
                         frame: frame_data.frame,
                         cell,
+                        explicit_null: value == NULL_CELL,
+                        data: extra_data(frame_data),
+                        source: source_token(value, opts),
                     });
                 }
             }
-            optimize_frames(&mut frames);
+            if opts.strict && !skipped.is_empty() {
+                warnings.push(Warning::FramesSkipped {
+                    table: name.clone(),
+                    layer: layer_name.clone(),
+                    tokens: skipped,
+                });
+            }
+            if opts.strict && !held.is_empty() {
+                warnings.push(Warning::HoldMarkersDropped {
+                    table: name.clone(),
+                    layer: layer_name.clone(),
+                    tokens: held,
+                });
+            }
+            optimize_frames(&mut frames, opts);
 
             layers.push(Layer {
                 name: layer_name,
+                track_no: track.track_no,
                 frames,
             });
         }
@@ -186,16 +598,142 @@ fn parse_tdts_timetable(name: String, time_table: TimeTable) -> Result<Timesheet
         name,
         frame_count,
         layers,
+        version: None,
+        cut: None,
+        comment: None,
     })
 }
 
-fn parse_xdts_cell_value(value: &str) -> Option<u16> {
-    if value == "SYMBOL_NULL_CELL" {
-        return Some(0);
+/// 显式空白 cel 的符号
+pub const NULL_CELL: &str = "SYMBOL_NULL_CELL";
+
+/// track_no 超出名称列表这么多时视为数据损坏
+const TRACK_NO_SLACK: usize = 16;
+
+/// 按 track_no 升序返回 track，并对明显超出名称列表的 track_no 发出警告
+fn ordered_tracks<'a>(
+    time_table: &TimeTable,
+    field: &'a Field,
+    names: &[String],
+    warnings: &mut Warnings,
+) -> Vec<&'a Track> {
+    let mut tracks: Vec<&Track> = field.tracks.iter().collect();
+    tracks.sort_by_key(|track| track.track_no);
+
+    for track in &tracks {
+        if track.track_no >= names.len() + TRACK_NO_SLACK {
+            warnings.push(Warning::TrackNoOutOfRange {
+                table: time_table.name.clone(),
+                track_no: track.track_no,
+                names_len: names.len(),
+            });
+        }
+    }
+
+    tracks
+}
+
+/// `--strict`：除 cel 所在 field 外还有数据的 field 会被忽略
+fn check_ignored_fields(
+    table: &str,
+    time_table: &TimeTable,
+    used: Option<&Field>,
+    warnings: &mut Warnings,
+) {
+    let fields: Vec<String> = time_table
+        .fields
+        .iter()
+        .filter(|field| !used.is_some_and(|used| std::ptr::eq(used, *field)))
+        .filter(|field| field.tracks.iter().any(|track| !track.frames.is_empty()))
+        .map(|field| match (&field.field_name, field.field_id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => format!("fieldId {}", id),
+            (None, None) => "(无 id)".to_string(),
+        })
+        .collect();
+
+    if !fields.is_empty() {
+        warnings.push(Warning::FieldsIgnored {
+            table: table.to_string(),
+            fields,
+        });
+    }
+}
+
+/// 层名称：优先取 header 的 names，其次取 track 自带的 label/name，
+/// 都没有时按 `--empty-layer-name` 模板生成，默认为 "Layer {n}"
+fn track_layer_name(names: &[String], track: &Track, opts: &Options) -> String {
+    names
+        .get(track.track_no)
+        .or(track.label.as_ref())
+        .cloned()
+        .unwrap_or_else(|| match &opts.empty_layer_name {
+            Some(template) => template.replace("{index}", &track.track_no.to_string()),
+            None => format!("Layer {}", track.track_no),
+        })
+}
+
+/// field 中是否有 track 自带名称
+fn has_track_labels(field: &Field) -> bool {
+    field.tracks.iter().any(|track| track.label.is_some())
+}
+
+/// 按 `--field-name` 查找 field；未指定或找不到时返回 None，回退到数字 id 匹配
+fn find_named_field<'a>(
+    time_table: &'a TimeTable,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Option<&'a Field> {
+    let field_name = opts.field_name.as_deref()?;
+    let field = time_table
+        .fields
+        .iter()
+        .find(|f| f.field_name.as_deref() == Some(field_name));
+
+    if field.is_none() {
+        warnings.push(Warning::FieldNameNotFound {
+            table: time_table.name.clone(),
+            field_name: field_name.to_string(),
+        });
+    }
+    field
+}
+
+/// 查找 field 对应的名称列表：优先按 fieldName 匹配，其次按 fieldId
+fn find_header_names<'a>(time_table: &'a TimeTable, field: &Field) -> Option<&'a Vec<String>> {
+    let headers = &time_table.time_table_headers;
+
+    field
+        .field_name
+        .as_ref()
+        .and_then(|name| headers.iter().find(|h| h.field_name.as_ref() == Some(name)))
+        .or_else(|| {
+            headers
+                .iter()
+                .find(|h| field.field_id.is_some() && h.field_id == field.field_id)
+        })
+        .map(|h| &h.names)
+}
+
+fn parse_xdts_cell_value(
+    value: &str,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Option<u32>> {
+    if value == NULL_CELL {
+        return Ok(Some(0));
     }
 
     if matches!(value, "SYMBOL_TICK_1" | "SYMBOL_TICK_2" | "SYMBOL_HYPHEN") {
-        return None; // 跳过这些特殊符号
+        return Ok(None); // 跳过这些特殊符号
+    }
+
+    // 带延长保持标记（如 "12E"）时，取前面的数字作为 cel，与普通曝光一样保持到下一关键帧
+    let value = strip_hold_marker(value, opts);
+
+    // 末尾是小数（如 "A12.5"）时按策略取整
+    if let Some(number) = trailing_decimal(value) {
+        return apply_fractional(value, number, opts.fractional, warnings).map(Some);
     }
 
     // 提取末尾的数字
@@ -203,38 +741,422 @@ fn parse_xdts_cell_value(value: &str) -> Option<u16> {
     let digits: String = digits.chars().rev().collect();
 
     if !digits.is_empty() {
-        digits.parse().ok()
+        Ok(digits.parse().ok())
+    } else {
+        Ok(None)
+    }
+}
+
+fn parse_tdts_cell_value(value: &str, opts: &Options, warnings: &mut Warnings) -> Result<u32> {
+    if value == NULL_CELL {
+        return Ok(0);
+    }
+
+    let value = strip_hold_marker(value, opts);
+
+    if let Some(number) = trailing_decimal(value) {
+        return apply_fractional(value, number, opts.fractional, warnings);
+    }
+
+    Ok(value.parse().unwrap_or(0))
+}
+
+/// 描述源数据值被解析为 cel 的过程（`--explain`）
+pub fn describe_cell_value(value: &str, opts: &Options) -> String {
+    if value == NULL_CELL {
+        return "显式空白".to_string();
+    }
+
+    let mut steps = Vec::new();
+    let stripped = strip_hold_marker(value, opts);
+    if stripped != value {
+        steps.push(format!("去掉延长保持标记 → \"{}\"", stripped));
+    }
+    match trailing_decimal(stripped) {
+        Some(number) => steps.push(format!("小数 {} 按 --fractional 策略取整", number)),
+        None => steps.push("取末尾数字".to_string()),
+    }
+    steps.join("，")
+}
+
+/// 去掉数字后面的延长保持标记，如 "12E" → "12"；没有标记时原样返回
+fn strip_hold_marker<'a>(value: &'a str, opts: &Options) -> &'a str {
+    for marker in opts.hold_markers() {
+        if let Some(rest) = value.strip_suffix(marker) {
+            if rest.ends_with(|c: char| c.is_ascii_digit()) {
+                return rest;
+            }
+        }
+    }
+    value
+}
+
+/// 提取末尾带小数点的数字，如 "A12.5" → 12.5；不含小数点时返回 None
+fn trailing_decimal(value: &str) -> Option<f64> {
+    let tail_len = value
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .count();
+    let tail = value[value.len() - tail_len..].trim_start_matches('.');
+
+    if tail.contains('.') && tail.starts_with(|c: char| c.is_ascii_digit()) {
+        tail.parse().ok()
     } else {
         None
     }
 }
 
-fn parse_tdts_cell_value(value: &str) -> u16 {
-    if value == "SYMBOL_NULL_CELL" {
-        return 0;
+/// 按策略把非整数 cel 值转换为整数
+fn apply_fractional(
+    token: &str,
+    number: f64,
+    policy: FractionalPolicy,
+    warnings: &mut Warnings,
+) -> Result<u32> {
+    if number.fract() == 0.0 {
+        return Ok(number.min(u32::MAX as f64) as u32);
     }
 
-    value.parse().unwrap_or(0)
+    let cell = match policy {
+        FractionalPolicy::Round => number.round(),
+        FractionalPolicy::Floor => number.floor(),
+        FractionalPolicy::Reject => anyhow::bail!("非整数 cel 值: '{}'", token),
+    };
+    let cell = cell.min(u32::MAX as f64) as u32;
+
+    warnings.push(Warning::FractionalCell {
+        token: token.to_string(),
+        cell,
+    });
+    Ok(cell)
 }
 
-fn optimize_frames(frames: &mut Vec<Frame>) {
+/// 帧上除 cel 以外还有其他 data 项或值时，原样保留全部 data 供 JSON 输出查看；
+/// 转换只使用第一个 data 项的第一个值
+fn extra_data(frame_data: &FrameData) -> Vec<DataValues> {
+    let single = frame_data.data.len() == 1 && frame_data.data[0].values.len() == 1;
+    if single {
+        return Vec::new();
+    }
+    frame_data
+        .data
+        .iter()
+        .map(|item| DataValues {
+            id: item.id,
+            values: item.values.clone(),
+        })
+        .collect()
+}
+
+/// 源数据中的关键帧按帧号升序排列（JSON 中不保证顺序）；同一帧号有多个时只保留最后一个
+fn sorted_frames(frames: &[FrameData]) -> Vec<&FrameData> {
+    let mut sorted: Vec<&FrameData> = frames.iter().collect();
+    // 稳定排序，同一帧号保持源数据中的先后顺序
+    sorted.sort_by_key(|frame_data| frame_data.frame);
+    let mut deduped: Vec<&FrameData> = Vec::with_capacity(sorted.len());
+    for frame_data in sorted {
+        match deduped.last_mut() {
+            Some(last) if last.frame == frame_data.frame => *last = frame_data,
+            _ => deduped.push(frame_data),
+        }
+    }
+    deduped
+}
+
+/// 需要时记录源数据中的原始值：`--preserve-symbols` 写入 JSON，`--explain` 用于追踪
+fn source_token(value: &str, opts: &Options) -> Option<String> {
+    (opts.preserve_symbols || opts.explain.is_some()).then(|| value.to_string())
+}
+
+fn optimize_frames(frames: &mut Vec<Frame>, opts: &Options) {
     if frames.is_empty() {
         return;
     }
 
     // 确保第一帧从 0 开始
     if frames[0].frame != 0 {
-        frames.insert(0, Frame { frame: 0, cell: 0 });
+        frames.insert(
+            0,
+            Frame {
+                frame: 0,
+                cell: 0,
+                explicit_null: false,
+                data: Vec::new(),
+                source: None,
+            },
+        );
     }
 
-    // 移除连续相同的 cell 值
+    // 移除连续相同的 cell 值（显式空白与未曝光的空白不合并）；
+    // 保留原始记号时记号帧都保留，普通 cel 只在原始值也相同时合并
     let mut i = frames.len() - 1;
     while i > 0 {
-        if frames[i].cell == frames[i - 1].cell {
+        let keep_source = opts.preserve_symbols
+            && (frames[i].source != frames[i - 1].source
+                || frames[i].source.as_deref().is_some_and(is_symbol_token));
+        if frames[i].cell == frames[i - 1].cell
+            && frames[i].explicit_null == frames[i - 1].explicit_null
+            && frames[i].data.is_empty()
+            && !keep_source
+        {
             frames.remove(i);
         }
-        if i > 0 {
-            i -= 1;
+        i -= 1;
+    }
+}
+
+/// 源数据值是否为记号（如 `SYMBOL_TICK_1`、`SYMBOL_NULL_CELL`）或其他不含 cel 编号的值
+fn is_symbol_token(value: &str) -> bool {
+    value.starts_with("SYMBOL_") || !value.contains(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一个 trackNo 为 `track_no`、名称列表长度为 `names` 的 XDTS 时间表
+    fn xdts_with_track(track_no: usize, names: usize) -> String {
+        let names: Vec<String> = (0..names).map(|i| format!("\"L{}\"", i)).collect();
+        format!(
+            r#"{{"timeTables": [{{"name": "T", "duration": 2,
+                "fields": [{{"fieldId": 0, "tracks": [
+                    {{"trackNo": 0, "frames": [{{"frame": 0, "data": [{{"values": ["1"]}}]}}]}},
+                    {{"trackNo": {}, "frames": [{{"frame": 0, "data": [{{"values": ["2"]}}]}}]}}
+                ]}}],
+                "timeTableHeaders": [{{"fieldId": 0, "names": [{}]}}]}}], "version": 5}}"#,
+            track_no,
+            names.join(", ")
+        )
+    }
+
+    fn parse_xdts(json: &str, warnings: &mut Warnings) -> Timesheet {
+        let path = Path::new("t.xdts");
+        load_xdts(path, json, &Options::default(), warnings).unwrap().remove(0)
+    }
+
+    fn layer_names(timesheet: &Timesheet) -> Vec<&str> {
+        timesheet.layers.iter().map(|layer| layer.name.as_str()).collect()
+    }
+
+    #[test]
+    fn track_no_within_slack_is_not_reported() {
+        let mut warnings = Warnings::default();
+        let track_no = 2 + TRACK_NO_SLACK - 1;
+        let timesheet = parse_xdts(&xdts_with_track(track_no, 2), &mut warnings);
+
+        let fallback = format!("Layer {}", track_no);
+        assert_eq!(layer_names(&timesheet), ["L0", fallback.as_str()]);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn track_no_far_beyond_names_is_reported() {
+        let mut warnings = Warnings::default();
+        let timesheet = parse_xdts(&xdts_with_track(1000, 2), &mut warnings);
+
+        assert_eq!(layer_names(&timesheet), ["L0", "Layer 1000"]);
+        let reported: Vec<&Warning> = warnings.iter().collect();
+        assert_eq!(
+            reported,
+            [&Warning::TrackNoOutOfRange {
+                table: "T".to_string(),
+                track_no: 1000,
+                names_len: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn hold_marker_keeps_its_cel() {
+        let json = r#"{"timeTables": [{"name": "T", "duration": 4,
+            "fields": [{"fieldId": 0, "tracks": [{"trackNo": 0, "frames": [
+                {"frame": 0, "data": [{"values": ["1"]}]},
+                {"frame": 2, "data": [{"values": ["12E"]}]}
+            ]}]}],
+            "timeTableHeaders": [{"fieldId": 0, "names": ["A"]}]}], "version": 5}"#;
+
+        let timesheet = parse_xdts(json, &mut Warnings::default());
+        assert_eq!(timesheet.layers[0].frames[1].cell, 12);
+    }
+
+    #[test]
+    fn one_line_and_indented_json_parse_the_same() {
+        let one_line = "exchangeDigitalTimeSheet Save Data{\"timeTables\":[{\"name\":\"T\",\
+            \"duration\":3,\"fields\":[{\"fieldId\":0,\"tracks\":[{\"trackNo\":0,\"frames\":[\
+            {\"frame\":0,\"data\":[{\"id\":0,\"values\":[\"1\"]}]},\
+            {\"frame\":2,\"data\":[{\"id\":0,\"values\":[\"2\"]}]}]}]}],\
+            \"timeTableHeaders\":[{\"fieldId\":0,\"names\":[\"A\"]}]}],\"version\":5}";
+        let indented = r#"exchangeDigitalTimeSheet Save Data
+{
+    "timeTables": [
+        {
+            "name": "T",
+            "duration": 3,
+            "fields": [
+                {
+                    "fieldId": 0,
+                    "tracks": [
+                        {
+                            "trackNo": 0,
+                            "frames": [
+                                { "frame": 0, "data": [ { "id": 0, "values": [ "1" ] } ] },
+                                { "frame": 2, "data": [ { "id": 0, "values": [ "2" ] } ] }
+                            ]
+                        }
+                    ]
+                }
+            ],
+            "timeTableHeaders": [ { "fieldId": 0, "names": [ "A" ] } ]
         }
+    ],
+    "version": 5
+}
+"#;
+
+        let parse = |content: &str| {
+            let (comment, json) = split_comment_line(content);
+            assert_eq!(comment, Some("exchangeDigitalTimeSheet Save Data"));
+            let mut warnings = Warnings::default();
+            let path = Path::new("t.xdts");
+            load_xdts(path, json, &Options::default(), &mut warnings).unwrap()
+        };
+        let from_one_line = parse(one_line);
+        let from_indented = parse(indented);
+
+        assert_eq!(from_one_line[0].layers[0].frames.len(), 2);
+        assert_eq!(format!("{:?}", from_one_line), format!("{:?}", from_indented));
+    }
+
+    #[test]
+    fn preserve_symbols_still_merges_plain_cels() {
+        let values = ["1", "1", "SYMBOL_TICK_1", "SYMBOL_TICK_1", "2", "2"];
+        let frames: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!(r#"{{"frame": {}, "data": [{{"values": ["{}"]}}]}}"#, i, v))
+            .collect();
+        let json = format!(
+            r#"{{"timeTables": [{{"name": "T", "duration": 6,
+                "fields": [{{"fieldId": 0, "tracks": [{{"trackNo": 0, "frames": [{}]}}]}}],
+                "timeTableHeaders": [{{"fieldId": 0, "names": ["A"]}}]}}], "version": 5}}"#,
+            frames.join(", ")
+        );
+        let opts = Options {
+            preserve_symbols: true,
+            format: crate::options::OutputFormat::Json,
+            ..Options::default()
+        };
+        let mut warnings = Warnings::default();
+        let timesheets = load_xdts(Path::new("t.xdts"), &json, &opts, &mut warnings).unwrap();
+
+        let kept: Vec<(u32, u32, Option<&str>)> = timesheets[0].layers[0]
+            .frames
+            .iter()
+            .map(|f| (f.frame, f.cell, f.source.as_deref()))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                (0, 1, Some("1")),
+                (2, 1, Some("SYMBOL_TICK_1")),
+                (3, 1, Some("SYMBOL_TICK_1")),
+                (4, 2, Some("2")),
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_marker_is_reported_under_strict() {
+        let json = r#"{"timeTables": [{"name": "T", "duration": 4,
+            "fields": [{"fieldId": 0, "tracks": [{"trackNo": 0, "frames": [
+                {"frame": 0, "data": [{"values": ["1"]}]},
+                {"frame": 2, "data": [{"values": ["12E"]}]}
+            ]}]}],
+            "timeTableHeaders": [{"fieldId": 0, "names": ["A"]}]}], "version": 5}"#;
+        let path = Path::new("t.xdts");
+
+        let mut warnings = Warnings::default();
+        let timesheets = load_xdts(path, json, &Options::default(), &mut warnings).unwrap();
+        assert_eq!(timesheets[0].layers[0].frames[1].cell, 12);
+        assert!(warnings.is_empty());
+
+        let opts = Options {
+            strict: true,
+            ..Options::default()
+        };
+        let mut warnings = Warnings::default();
+        load_xdts(path, json, &opts, &mut warnings).unwrap();
+        let reported: Vec<&Warning> = warnings.iter().collect();
+        assert_eq!(
+            reported,
+            [&Warning::HoldMarkersDropped {
+                table: "t.xdts->T".to_string(),
+                layer: "A".to_string(),
+                tokens: vec!["12E".to_string()],
+            }]
+        );
+        assert!(reported[0].is_data_loss());
+    }
+
+    #[test]
+    fn split_comment_line_with_comment() {
+        assert_eq!(
+            split_comment_line("exchangeDigitalTimeSheet Save Data\n{\"a\": 1}"),
+            (Some("exchangeDigitalTimeSheet Save Data"), "\n{\"a\": 1}")
+        );
+        assert_eq!(
+            split_comment_line("exchangeDigitalTimeSheet Save Data{\"a\": 1}"),
+            (Some("exchangeDigitalTimeSheet Save Data"), "{\"a\": 1}")
+        );
+    }
+
+    #[test]
+    fn split_comment_line_without_comment() {
+        assert_eq!(split_comment_line("  \n{\"a\": 1}"), (None, "{\"a\": 1}"));
+        assert_eq!(split_comment_line("[1]"), (None, "[1]"));
+    }
+
+    #[test]
+    fn split_comment_line_with_hash_comment() {
+        // 整行注释中的 `{` 不是 JSON 的开始
+        assert_eq!(
+            split_comment_line("# saved by {tool}\n{\"a\": 1}"),
+            (Some("# saved by {tool}"), "\n{\"a\": 1}")
+        );
+    }
+
+    #[test]
+    fn split_comment_line_with_bom() {
+        assert_eq!(split_comment_line("\u{feff}{\"a\": 1}"), (None, "{\"a\": 1}"));
+        assert_eq!(
+            split_comment_line("\u{feff}exchangeDigitalTimeSheet Save Data\n{}"),
+            (Some("exchangeDigitalTimeSheet Save Data"), "\n{}")
+        );
+    }
+
+    #[test]
+    fn sorted_frames_orders_and_keeps_last_duplicate() {
+        let frame = |frame: u32, value: &str| FrameData {
+            frame,
+            data: vec![DataItem {
+                id: None,
+                values: vec![value.to_string()],
+            }],
+        };
+        let frames = vec![
+            frame(4, "c"),
+            frame(0, "a"),
+            frame(2, "b1"),
+            frame(6, "d"),
+            frame(2, "b2"),
+            frame(2, "b3"),
+        ];
+
+        let sorted: Vec<(u32, &str)> = sorted_frames(&frames)
+            .iter()
+            .map(|f| (f.frame, f.data[0].values[0].as_str()))
+            .collect();
+        assert_eq!(sorted, [(0, "a"), (2, "b3"), (4, "c"), (6, "d")]);
     }
 }
@@ -0,0 +1,1092 @@
+//! 单个源文件的转换流程：解析、预处理，按输出格式编码写入，以及解包 `.stsx` 和反向转换 `.sts`
+//!
+//! 命令行程序负责参数、消息框和批处理循环，每个源文件交给 [`process_file`]
+//! （或带 `--timeout` 限时的 [`process_file_with_timeout`]）处理。
+
+use crate::options::{Options, OutputFormat};
+use crate::types::SourceFormat;
+use crate::warning::Warnings;
+use crate::{
+    container, converter, index, json, longcsv, otcsv, parser, preview, provenance, sts, types,
+    warning, xdts,
+};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// 写入前的确认提示：显示标题和说明，返回是否继续
+pub type ConfirmPrompt = fn(&str, &str) -> bool;
+
+/// 格式化数字为带千位分隔符的字符串
+pub fn format_number(n: u64) -> String {
+    let s = n.to_string();
+    let mut result = String::new();
+
+    for (count, c) in s.chars().rev().enumerate() {
+        if count > 0 && count % 3 == 0 {
+            result.push(',');
+        }
+        result.push(c);
+    }
+
+    result.chars().rev().collect()
+}
+
+/// 一次转换失败的记录
+#[derive(Debug)]
+pub struct Failure {
+    /// 源文件路径
+    pub source: PathBuf,
+    /// 失败的时间表名称（整个文件失败时为 None）
+    pub timesheet: Option<String>,
+    /// 错误信息
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(source: &Path, timesheet: Option<&str>, error: &anyhow::Error) -> Self {
+        Failure {
+            source: source.to_path_buf(),
+            timesheet: timesheet.map(str::to_string),
+            message: format!("{:#}", error),
+        }
+    }
+}
+
+/// 单个源文件的处理结果
+#[derive(Debug, Default)]
+pub struct ProcessOutcome {
+    /// 生成的输出文件
+    pub output_paths: Vec<PathBuf>,
+    /// 生成的预览图等附带文件
+    pub sidecar_paths: Vec<PathBuf>,
+    /// 因输出已是最新而跳过的文件数
+    pub skipped_current: usize,
+    /// 用户在确认对话框中取消了转换
+    pub cancelled: bool,
+    /// 转换失败的时间表
+    pub failures: Vec<Failure>,
+    /// 解析和转换过程中的警告
+    pub warnings: Warnings,
+    /// `--index-csv` 的索引行
+    pub index_rows: Vec<index::IndexRow>,
+    /// 按源文件顺序汇总的说明：`--dry-run` 的写入计划、`--diff-against` 变化的层
+    pub notes: Vec<String>,
+    /// 源文件格式（解包 `.stsx` 容器时为 None）
+    pub source_format: Option<SourceFormat>,
+    /// `--state-file` 中记录为已完成，没有处理
+    pub already_done: bool,
+}
+
+impl ProcessOutcome {
+    /// 记录说明，非静默时同时打印
+    fn note(&mut self, lines: Vec<String>, quiet: bool) {
+        if !quiet {
+            for line in &lines {
+                println!("{}", line);
+            }
+        }
+        self.notes.extend(lines);
+    }
+}
+
+/// `--timeout` 时工作线程发给主线程的消息
+enum WorkerEvent {
+    /// 开始等待写入确认，暂停计时
+    Confirming,
+    /// 确认结束，重新开始计时
+    Confirmed,
+    /// 处理结束
+    Done(Result<ProcessOutcome>),
+}
+
+/// `--timeout` 的取消控制：主线程超时后置位，工作线程每次写入前检查；
+/// 确认提示前后通知主线程暂停和重新开始计时
+#[derive(Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    events: Option<mpsc::Sender<WorkerEvent>>,
+}
+
+impl Cancellation {
+    /// 已超时则返回错误，不再写入
+    pub fn check(&self) -> Result<()> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            anyhow::bail!("处理已超时，取消写入");
+        }
+        Ok(())
+    }
+
+    /// 显示写入确认，等待确认的时间不计入超时
+    fn confirm(&self, prompt: ConfirmPrompt, title: &str, message: &str) -> bool {
+        self.notify(WorkerEvent::Confirming);
+        let confirmed = prompt(title, message);
+        self.notify(WorkerEvent::Confirmed);
+        confirmed
+    }
+
+    fn notify(&self, event: WorkerEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+}
+
+/// 按 `--timeout` 限制单个文件的处理时间：在工作线程中运行 [`process_file`]，
+/// 超时则报告失败并继续（工作线程无法强制终止，会在后台自行结束，但不再写入文件）。
+/// 写入确认提示期间不计时，确认后重新开始计时
+pub fn process_file_with_timeout(
+    input_path: &Path,
+    output_dir: Option<&Path>,
+    quiet: bool,
+    confirm: Option<ConfirmPrompt>,
+    opts: &Options,
+    outputs: &OutputPaths,
+) -> Result<ProcessOutcome> {
+    let timeout = match opts.timeout {
+        Some(timeout) => timeout,
+        None => {
+            let cancel = Cancellation::default();
+            return process_file(input_path, output_dir, quiet, confirm, opts, outputs, &cancel);
+        }
+    };
+
+    let input = input_path.to_path_buf();
+    let output_dir = output_dir.map(Path::to_path_buf);
+    let opts = opts.clone();
+    let outputs = outputs.clone();
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let cancel = Cancellation {
+        cancelled: Arc::clone(&cancelled),
+        events: Some(sender.clone()),
+    };
+
+    thread::Builder::new()
+        .name(format!("convert {}", input.display()))
+        .spawn(move || {
+            let dir = output_dir.as_deref();
+            let result = process_file(&input, dir, quiet, confirm, &opts, &outputs, &cancel);
+            // 主线程已超时返回时接收端已关闭，结果直接丢弃
+            let _ = sender.send(WorkerEvent::Done(result));
+        })
+        .context("无法创建转换线程")?;
+
+    let mut deadline = Some(Instant::now() + timeout);
+    loop {
+        let event = match deadline {
+            Some(deadline) => {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(WorkerEvent::Done(result)) => return result,
+            Ok(WorkerEvent::Confirming) => deadline = None,
+            Ok(WorkerEvent::Confirmed) => deadline = Some(Instant::now() + timeout),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                cancelled.store(true, Ordering::SeqCst);
+                anyhow::bail!("处理超时 (超过 {} 秒)", timeout.as_secs_f64())
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => anyhow::bail!("转换线程异常退出"),
+        }
+    }
+}
+
+/// 处理一个源文件：`.stsx` 解包，`.sts` 反向转换为 XDTS，xdts/tdts 按选项转换并写入；
+/// `confirm` 不为 `None` 时写入前显示预览，由用户确认
+pub fn process_file(
+    input_path: &Path,
+    output_dir: Option<&Path>,
+    quiet: bool,
+    confirm: Option<ConfirmPrompt>,
+    opts: &Options,
+    outputs: &OutputPaths,
+    cancel: &Cancellation,
+) -> Result<ProcessOutcome> {
+    let verbose = opts.verbose;
+    if is_container_file(input_path) {
+        return extract_container(input_path, output_dir, quiet, cancel);
+    }
+    if is_sts_file(input_path) {
+        return sts_to_xdts(input_path, output_dir, quiet, opts, cancel);
+    }
+
+    // 加载时间表
+    if !verbose && !quiet {
+        println!("正在加载: {}", input_path.display());
+    }
+
+    let mut outcome = ProcessOutcome::default();
+    let (source_format, mut timesheets) =
+        parser::load_timesheets(input_path, opts, &mut outcome.warnings)?;
+    outcome.source_format = Some(source_format);
+
+    preprocess_timesheets(&mut timesheets, opts, &mut outcome.warnings)?;
+
+    // 只保留与旧 STS 相比有变化的层
+    if let Some(old_path) = &opts.diff_against {
+        let old = sts::load_sts(old_path)?;
+        for ts in &mut timesheets {
+            let layer_count = ts.layers.len();
+            let changed = converter::diff_layers(ts, &old, opts)?;
+            let line = if changed.is_empty() {
+                format!("{}: 与 {} 相比没有变化的层", ts.name, old_path.display())
+            } else {
+                format!(
+                    "{}: 变化的层 {} (未变化 {} 层)",
+                    ts.name,
+                    changed.join(", "),
+                    layer_count - changed.len()
+                )
+            };
+            outcome.note(vec![line], quiet);
+        }
+    }
+
+    if let Some(target) = &opts.explain {
+        let mut found = false;
+        for ts in &timesheets {
+            if let Some(lines) = converter::explain_cell(ts, target, opts)? {
+                outcome.note(lines, quiet);
+                found = true;
+            }
+        }
+        if !found {
+            outcome.note(vec![format!("--explain: 没有名为 '{}' 的层", target.layer)], quiet);
+        }
+    }
+
+    if !verbose && !quiet {
+        println!("找到 {} 个时间表", timesheets.len());
+        if let Some(version) = timesheets.first().and_then(|ts| ts.version) {
+            println!("格式版本: {}", version);
+        }
+    }
+
+    // 只校验：检查各层帧范围后返回，不写入文件
+    if opts.validate_only {
+        for ts in &timesheets {
+            let before = outcome.warnings.len();
+            converter::check_frame_spans(ts, &mut outcome.warnings);
+            converter::check_gaps(ts, opts, &mut outcome.warnings);
+            if let Some(threshold) = opts.dense_layer_threshold {
+                converter::check_dense_layers(ts, threshold, &mut outcome.warnings);
+            }
+            if !quiet {
+                let issues = outcome.warnings.len() - before;
+                if issues == 0 {
+                    println!("✓ 校验通过: {}", ts.name);
+                } else {
+                    println!("⚠ {}: {} 项问题", ts.name, issues);
+                }
+            }
+        }
+        return Ok(outcome);
+    }
+
+    // 确定输出目录
+    let output_dir = match output_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => input_path
+            .parent()
+            .context("无法获取父目录")?
+            .to_path_buf(),
+    };
+
+    // 只输出概要：每个源文件一个 JSON 数组，不展开帧数据
+    if opts.metadata_only {
+        let metadata_path = output_dir.join(format!(
+            "{}.json",
+            input_path.file_stem().unwrap().to_string_lossy()
+        ));
+        if opts.overwrite_if_newer && is_output_current(input_path, &metadata_path) {
+            outcome.skipped_current += 1;
+            return Ok(outcome);
+        }
+        cancel.check()?;
+        json::save_metadata(&timesheets, &metadata_path)?;
+        if !verbose && !quiet {
+            println!(
+                "✓ 已输出概要: {}",
+                metadata_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+        outcome.output_paths.push(metadata_path);
+        throttle_write(opts);
+        return Ok(outcome);
+    }
+
+    // 按 cut 拆分为多个 XDTS 文件
+    if opts.split_cuts {
+        let mut cuts: Vec<(&str, Vec<&types::Timesheet>)> = Vec::new();
+        for ts in &timesheets {
+            let cut = ts
+                .cut
+                .as_deref()
+                .context("--split-cuts 只能用于 tdts 文件")?;
+            match cuts.iter_mut().find(|(name, _)| *name == cut) {
+                Some((_, group)) => group.push(ts),
+                None => cuts.push((cut, vec![ts])),
+            }
+        }
+
+        for (cut, group) in cuts {
+            let safe_cut = cut.replace(['/', '\\', ':'], "_");
+            let xdts_path = output_dir.join(format!("{}.xdts", safe_cut));
+            if opts.overwrite_if_newer && is_output_current(input_path, &xdts_path) {
+                outcome.skipped_current += 1;
+                continue;
+            }
+            // 注释行：--xdts-comment 优先，其次是源文件的原注释
+            let comment = opts
+                .xdts_comment
+                .as_deref()
+                .or(group[0].comment.as_deref())
+                .unwrap_or(xdts::DEFAULT_COMMENT);
+            let root = xdts::timesheets_to_xdts(cut, &group);
+            cancel.check()?;
+            xdts::save_xdts(&root, comment, &xdts_path, opts.atomic, opts.buffer_size())?;
+            if !verbose && !quiet {
+                println!(
+                    "✓ cut {} ({} 个时间表): {}",
+                    cut,
+                    group.len(),
+                    xdts_path.file_name().unwrap().to_string_lossy()
+                );
+            }
+            outcome.output_paths.push(xdts_path);
+            throttle_write(opts);
+        }
+        return Ok(outcome);
+    }
+
+    let mut container_entries = Vec::new();
+
+    let container_path = output_dir.join(format!(
+        "{}.stsx",
+        input_path.file_stem().unwrap().to_string_lossy()
+    ));
+    if opts.container && opts.overwrite_if_newer && is_output_current(input_path, &container_path) {
+        if !quiet {
+            println!(
+                "- 已是最新，跳过: {}",
+                container_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+        outcome.skipped_current += 1;
+        return Ok(outcome);
+    }
+
+    // 多时间表文件按源文件建子目录（容器模式只输出一个文件，不需要）
+    let use_subdir = opts.subdir_per_source && timesheets.len() > 1 && !opts.container;
+    let output_dir = if use_subdir {
+        output_dir.join(input_path.file_stem().unwrap())
+    } else {
+        output_dir
+    };
+
+    // 生成输出文件名；与本次运行中已生成的文件重名时改名，容器中的条目只在容器内去重
+    let output_names: Vec<String> = timesheets
+        .iter()
+        .enumerate()
+        .map(|(i, ts)| output_file_name(input_path, timesheets.len(), i, ts, opts))
+        .collect();
+    let output_names = if opts.container {
+        let entries = OutputPaths::default();
+        dedupe_output_names(output_names, &timesheets, &container_path, &entries, &mut outcome.warnings)
+    } else {
+        dedupe_output_names(output_names, &timesheets, &output_dir, outputs, &mut outcome.warnings)
+    };
+
+    // 试运行：按正常流程编码并校验，只列出计划，不写入
+    if opts.dry_run {
+        for (ts, output_name) in timesheets.iter().zip(&output_names) {
+            let output_path = if opts.container {
+                container_path.clone()
+            } else {
+                output_dir.join(output_name)
+            };
+            let plan =
+                plan_timesheet(ts, input_path, &output_path, verbose, opts, &mut outcome.warnings);
+            let lines = match plan {
+                Ok(lines) => lines,
+                Err(e) => {
+                    outcome.failures.push(Failure::new(input_path, Some(&ts.name), &e));
+                    vec![format!("✗ {}: {}", ts.name, e)]
+                }
+            };
+            outcome.note(lines, quiet);
+        }
+        return Ok(outcome);
+    }
+
+    // 写入前预览并确认
+    if let Some(prompt) = confirm {
+        let mut message = String::new();
+        for (ts, output_name) in timesheets.iter().zip(&output_names) {
+            let duration = opts
+                .duration_seconds(ts.frame_count as usize)
+                .map(|seconds| format!(" ({:.2} 秒)", seconds))
+                .unwrap_or_default();
+            message.push_str(&format!(
+                "{}\n  {} 层, {} 帧{} → {}\n",
+                ts.name,
+                ts.layers.len(),
+                ts.frame_count,
+                duration,
+                output_name
+            ));
+        }
+        if opts.container {
+            message.push_str(&format!(
+                "\n打包为: {}\n",
+                container_path.file_name().unwrap().to_string_lossy()
+            ));
+        }
+
+        if !cancel.confirm(prompt, "确认转换", &message) {
+            outcome.cancelled = true;
+            return Ok(outcome);
+        }
+    }
+
+    if use_subdir {
+        cancel.check()?;
+        std::fs::create_dir_all(&output_dir)
+            .with_context(|| format!("无法创建输出目录: {}", output_dir.display()))?;
+    }
+
+    // 转换每个时间表：各时间表在线程池中并行编码和写入，结果按原顺序汇总，
+    // 输出路径、警告和打印顺序与串行处理一致
+    let results: Vec<(Warnings, Result<TimesheetOutput>)> = timesheets
+        .par_iter()
+        .zip(output_names.par_iter())
+        .map(|(ts, output_name)| {
+            let mut warnings = Warnings::default();
+            let target = TimesheetTarget {
+                input_path,
+                output_dir: &output_dir,
+                output_name,
+            };
+            let result = convert_timesheet(ts, &target, verbose, opts, cancel, &mut warnings);
+            (warnings, result)
+        })
+        .collect();
+
+    for ((ts, output_name), (warnings, result)) in
+        timesheets.iter().zip(output_names).zip(results)
+    {
+        outcome.warnings.extend(warnings);
+        match result {
+            // 容器模式：只编码，最后统一写入 .stsx
+            Ok(TimesheetOutput::Encoded {
+                data,
+                report,
+                preview,
+            }) => {
+                outcome.sidecar_paths.extend(preview);
+                outcome.index_rows.push(index::IndexRow {
+                    output: container_path.clone(),
+                    source: input_path.to_path_buf(),
+                    timesheet: ts.name.clone(),
+                    layer_count: ts.layers.len(),
+                    frame_count: report.frame_count,
+                    size: data.len() as u64,
+                });
+                container_entries.push(container::ContainerEntry {
+                    name: output_name,
+                    data,
+                });
+            }
+            Ok(TimesheetOutput::Current { path }) => {
+                if !quiet {
+                    println!("- 已是最新，跳过: {}", path.file_name().unwrap().to_string_lossy());
+                }
+                outcome.skipped_current += 1;
+            }
+            Ok(TimesheetOutput::Written {
+                path,
+                report,
+                preview,
+            }) => {
+                outcome.sidecar_paths.extend(preview);
+                outcome.index_rows.push(index::IndexRow {
+                    output: path.clone(),
+                    source: input_path.to_path_buf(),
+                    timesheet: ts.name.clone(),
+                    layer_count: ts.layers.len(),
+                    frame_count: report.frame_count,
+                    size: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                });
+                if !verbose && !quiet {
+                    println!("✓ 已转换: {}", path.file_name().unwrap().to_string_lossy());
+                    if report.trimmed_frames > 0 {
+                        println!("  裁剪末尾空白帧: {} 帧", report.trimmed_frames);
+                    }
+                    if let Some(seconds) = opts.duration_seconds(report.frame_count) {
+                        println!("  时长: {} 帧, {:.2} 秒", report.frame_count, seconds);
+                    }
+                    if !report.layer_ranges.is_empty() {
+                        println!("  画面范围: {}", converter::describe_ranges(&report.layer_ranges));
+                    }
+                    let gaps = converter::describe_gaps(&report.layer_ranges);
+                    if !gaps.is_empty() {
+                        println!("  空白间隙: {}", gaps);
+                    }
+                    for adjustment in &report.span_adjustments {
+                        println!("  层时长 (层 '{}'): {}", adjustment.layer, adjustment.describe());
+                    }
+                    for mapping in &report.cel_substitutions {
+                        if let Some(layer) = &mapping.layer {
+                            println!("  cel 等价替换 (层 '{}'): {}", layer, mapping.describe());
+                        }
+                    }
+                    for mapping in &report.cell_mappings {
+                        match &mapping.layer {
+                            Some(layer) => {
+                                println!("  cel 重新编号 (层 '{}'): {}", layer, mapping.describe())
+                            }
+                            None => println!("  cel 重新编号: {}", mapping.describe()),
+                        }
+                    }
+                }
+                outcome.output_paths.push(path);
+            }
+            Err(e) => {
+                if !quiet {
+                    eprintln!("✗ 转换失败: {}", ts.name);
+                    eprintln!("  错误: {}", e);
+                }
+                outcome.failures.push(Failure::new(input_path, Some(&ts.name), &e));
+            }
+        }
+    }
+
+    if !container_entries.is_empty() {
+        cancel.check()?;
+        container::save_container(
+            &container_entries,
+            &container_path,
+            opts.atomic,
+            opts.buffer_size(),
+        )?;
+        if !verbose && !quiet {
+            println!(
+                "✓ 已打包 {} 个时间表: {}",
+                container_entries.len(),
+                container_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+        outcome.output_paths.push(container_path);
+        throttle_write(opts);
+    }
+
+    Ok(outcome)
+}
+
+/// 转换前的预处理：重新计时、筛选/重排/去重/反转层、按前缀拆分；
+/// `--strict` 时此阶段有数据丢失则失败
+pub fn preprocess_timesheets(
+    timesheets: &mut Vec<types::Timesheet>,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    // 重新计时
+    if let Some(factor) = opts.retime {
+        for ts in timesheets.iter_mut() {
+            converter::retime(ts, factor, warnings);
+        }
+    }
+
+    // 只保留名单中的层
+    if let Some(names) = &opts.keep_layers {
+        for ts in timesheets.iter_mut() {
+            converter::keep_layers(ts, names, warnings);
+        }
+    }
+
+    // 按列表重排层
+    if let Some(order) = &opts.layer_order {
+        for ts in timesheets.iter_mut() {
+            converter::reorder_layers(ts, order, warnings);
+        }
+    }
+
+    // 移除重复的层
+    if opts.dedupe_layers {
+        for ts in timesheets.iter_mut() {
+            converter::dedupe_layers(ts, opts, warnings);
+        }
+    }
+
+    // 反转层顺序：帧数据区和层名称区都按反转后的顺序写入
+    if opts.reverse_layers {
+        for ts in timesheets.iter_mut() {
+            ts.layers.reverse();
+        }
+    }
+
+    // 按层名称前缀拆分为多个时间表，每组输出一个文件
+    if opts.split_by_prefix {
+        let delimiter = opts.prefix_delimiter.as_deref().unwrap_or("_");
+        *timesheets = std::mem::take(timesheets)
+            .into_iter()
+            .flat_map(|ts| converter::split_by_prefix(ts, delimiter))
+            .collect();
+    }
+
+    // 严格模式：解析和预处理阶段有数据丢失时整个文件失败
+    if opts.strict {
+        converter::ensure_lossless(warnings, 0)?;
+    }
+
+    Ok(())
+}
+
+/// 单个时间表的输出位置
+struct TimesheetTarget<'a> {
+    input_path: &'a Path,
+    output_dir: &'a Path,
+    output_name: &'a str,
+}
+
+/// 单个时间表的转换结果
+enum TimesheetOutput {
+    /// 容器模式：只编码，由调用方统一写入 .stsx
+    Encoded {
+        data: Vec<u8>,
+        report: converter::ConvertReport,
+        preview: Option<PathBuf>,
+    },
+    /// 已写入输出文件
+    Written {
+        path: PathBuf,
+        report: converter::ConvertReport,
+        preview: Option<PathBuf>,
+    },
+    /// 输出已是最新，跳过
+    Current { path: PathBuf },
+}
+
+/// 转换并保存一个时间表；不打印结果，可在工作线程中并行调用
+fn convert_timesheet(
+    ts: &types::Timesheet,
+    target: &TimesheetTarget,
+    verbose: bool,
+    opts: &Options,
+    cancel: &Cancellation,
+    warnings: &mut Warnings,
+) -> Result<TimesheetOutput> {
+    if opts.container {
+        let (data, report) = converter::encode_sts(ts, verbose, opts, warnings)?;
+        cancel.check()?;
+        let preview = write_preview(ts, target.output_name, opts, warnings);
+        return Ok(TimesheetOutput::Encoded {
+            data,
+            report,
+            preview,
+        });
+    }
+
+    let output_path = target.output_dir.join(target.output_name);
+
+    if opts.overwrite_if_newer && is_output_current(target.input_path, &output_path) {
+        return Ok(TimesheetOutput::Current { path: output_path });
+    }
+
+    let provenance = opts
+        .embed_provenance
+        .then(|| provenance::Provenance::new(target.input_path, &ts.name));
+    cancel.check()?;
+    let report = match opts.format {
+        OutputFormat::Sts => converter::save_sts(ts, &output_path, verbose, opts, warnings)?,
+        OutputFormat::Otcsv => otcsv::save_otcsv(
+            ts,
+            &output_path,
+            verbose,
+            opts,
+            provenance.as_ref(),
+            warnings,
+        )?,
+        OutputFormat::LongCsv => longcsv::save_long_csv(
+            ts,
+            &output_path,
+            verbose,
+            opts,
+            provenance.as_ref(),
+            warnings,
+        )?,
+        // JSON 保留关键帧形式，不经过展开
+        OutputFormat::Json => {
+            json::save_json(ts, &output_path, verbose, provenance.as_ref(), opts.cell_as_hex)?;
+            converter::ConvertReport {
+                frame_count: ts.frame_count as usize,
+                ..Default::default()
+            }
+        }
+    };
+
+    cancel.check()?;
+    let preview = write_preview(ts, target.output_name, opts, warnings);
+    throttle_write(opts);
+    Ok(TimesheetOutput::Written {
+        path: output_path,
+        report,
+        preview,
+    })
+}
+
+/// `--dry-run` 时编码一个时间表（不写入），生成计划说明；
+/// `verbose` 时包含大小明细和该时间表产生的警告
+fn plan_timesheet(
+    ts: &types::Timesheet,
+    input_path: &Path,
+    output_path: &Path,
+    verbose: bool,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Result<Vec<String>> {
+    let before = warnings.len();
+    let provenance = opts
+        .embed_provenance
+        .then(|| provenance::Provenance::new(input_path, &ts.name));
+
+    let (size, breakdown, frame_count) = match opts.format {
+        OutputFormat::Sts => {
+            let (data, report) = converter::encode_sts(ts, false, opts, warnings)?;
+            let sizes = converter::StsSizes::of(
+                &data,
+                ts.layers.len(),
+                report.frame_count,
+                opts.cell_width,
+            );
+            let mut breakdown = format!(
+                " (文件头 {} + 帧数据 {} + 名称区 {}",
+                sizes.header, sizes.frame_data, sizes.names
+            );
+            if sizes.footer > 0 {
+                breakdown.push_str(&format!(" + CRC32 尾部 {}", sizes.footer));
+            }
+            breakdown.push(')');
+            (sizes.total(), breakdown, report.frame_count)
+        }
+        OutputFormat::Otcsv | OutputFormat::LongCsv => {
+            let (text, report) = if opts.format == OutputFormat::Otcsv {
+                otcsv::encode_otcsv(ts, false, opts, warnings)?
+            } else {
+                longcsv::encode_long_csv(ts, false, opts, warnings)?
+            };
+            let comment = provenance
+                .as_ref()
+                .map_or(0, |provenance| provenance.comment_line().len() + 1);
+            (text.len() + comment, String::new(), report.frame_count)
+        }
+        OutputFormat::Json => {
+            let text = json::encode_json(ts, provenance.as_ref(), opts.cell_as_hex)?;
+            (text.len(), String::new(), ts.frame_count as usize)
+        }
+    };
+
+    let current = opts.overwrite_if_newer && is_output_current(input_path, output_path);
+    let skip_note = if current { " (已是最新，将跳过)" } else { "" };
+
+    if !verbose {
+        return Ok(vec![format!(
+            "计划: {} → {} ({} 字节){}",
+            ts.name,
+            output_path.display(),
+            size,
+            skip_note
+        )]);
+    }
+
+    let mut lines = vec![
+        format!("计划: {}", ts.name),
+        format!("  输出: {}{}", output_path.display(), skip_note),
+        format!("  层数: {}, 帧数: {}", ts.layers.len(), frame_count),
+        format!("  预计大小: {} 字节{}", size, breakdown),
+    ];
+    for warning in warnings.iter().skip(before) {
+        lines.push(format!("  ⚠ {}", warning));
+    }
+    Ok(lines)
+}
+
+/// 按 `--write-delay` 在两次写入之间暂停，减轻慢速网络共享的压力
+fn throttle_write(opts: &Options) {
+    if !opts.write_delay.is_zero() {
+        thread::sleep(opts.write_delay);
+    }
+}
+
+/// 按 --preview-png 生成曝光预览图；失败只警告，不影响 STS 输出
+fn write_preview(
+    ts: &types::Timesheet,
+    output_name: &str,
+    opts: &Options,
+    warnings: &mut Warnings,
+) -> Option<PathBuf> {
+    let preview_dir = opts.preview_png.as_ref()?;
+    let png_path = preview_dir.join(Path::new(output_name).with_extension("png"));
+
+    let result = std::fs::create_dir_all(preview_dir)
+        .with_context(|| format!("无法创建预览目录: {}", preview_dir.display()))
+        // 展开时的警告已在转换 STS 时报告过，这里不再重复收集
+        .and_then(|_| converter::expand_timesheet(ts, false, opts, &mut Warnings::default()))
+        .and_then(|(cells, report)| {
+            preview::save_preview_png(&cells, report.frame_count, &png_path)
+        });
+
+    match result {
+        Ok(()) => Some(png_path),
+        Err(e) => {
+            warnings.push(warning::Warning::PreviewFailed {
+                message: format!("{:#}", e),
+            });
+            None
+        }
+    }
+}
+
+/// 生成时间表对应的输出文件名
+pub fn output_file_name(
+    input_path: &Path,
+    count: usize,
+    index: usize,
+    ts: &types::Timesheet,
+    opts: &Options,
+) -> String {
+    let format = opts.format;
+    if count == 1 {
+        format!(
+            "{}.{}",
+            input_path.file_stem().unwrap().to_string_lossy(),
+            format.extension()
+        )
+    } else {
+        // 文件名已经以源文件名开头（或已在以源文件命名的子目录中）时，
+        // 时间表名称不再重复 "源文件名->" 前缀
+        let subdir = opts.subdir_per_source && !opts.container;
+        let name = match ts.name.split_once("->") {
+            Some((_, table)) if subdir || opts.strip_extension_in_name => table,
+            _ => ts.name.as_str(),
+        };
+        let safe_name = name.replace(['/', '\\', ':'], "_");
+        // 截断到 100 字节以内，且不能切在多字节字符中间
+        let cut = safe_name
+            .char_indices()
+            .map(|(i, c)| i + c.len_utf8())
+            .take_while(|&end| end <= 100)
+            .last()
+            .unwrap_or(0);
+        let safe_name = &safe_name[..cut];
+        if subdir {
+            return format!("{:03}_{}.{}", index, safe_name, format.extension());
+        }
+        format!(
+            "{}_{:03}_{}.{}",
+            input_path.file_stem().unwrap().to_string_lossy(),
+            index,
+            safe_name,
+            format.extension()
+        )
+    }
+}
+
+/// 本次运行中已生成的输出路径，各源文件（包括并行转换的）共享。
+/// 生成的路径已被占用时——同一源文件中的同名时间表，或 `--output-dir` 下来自不同文件夹的
+/// 同名源文件——依次追加 " (2)"、" (3)" 等，避免互相覆盖。
+/// 按不区分大小写比较（Windows 文件系统不区分大小写）；并行转换时先分配的文件保留原名
+#[derive(Debug, Clone, Default)]
+pub struct OutputPaths {
+    used: Arc<Mutex<HashSet<String>>>,
+}
+
+impl OutputPaths {
+    /// 分配 `dir` 中的文件名 `name`，返回实际使用的文件名（已被占用时追加编号）
+    pub fn claim(&self, dir: &Path, name: &str) -> String {
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        let mut take = |name: &str| used.insert(dir.join(name).to_string_lossy().to_lowercase());
+        if take(name) {
+            return name.to_string();
+        }
+        let path = Path::new(name);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        (2..)
+            .map(|n| format!("{} ({}).{}", stem, n, extension))
+            .find(|candidate| take(candidate))
+            .unwrap()
+    }
+}
+
+/// 为各时间表在 `dir` 中分配输出文件名，改名的报告为警告
+fn dedupe_output_names(
+    names: Vec<String>,
+    timesheets: &[types::Timesheet],
+    dir: &Path,
+    outputs: &OutputPaths,
+    warnings: &mut Warnings,
+) -> Vec<String> {
+    names
+        .into_iter()
+        .zip(timesheets)
+        .map(|(name, ts)| {
+            let renamed = outputs.claim(dir, &name);
+            if renamed != name {
+                warnings.push(warning::Warning::OutputRenamed {
+                    table: ts.name.clone(),
+                    name,
+                    renamed: renamed.clone(),
+                });
+            }
+            renamed
+        })
+        .collect()
+}
+
+/// 输出文件存在且修改时间不早于源文件时视为最新
+fn is_output_current(source: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    match (modified(source), modified(output)) {
+        (Some(source_time), Some(output_time)) => output_time >= source_time,
+        _ => false,
+    }
+}
+
+pub fn is_container_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().eq_ignore_ascii_case("stsx"))
+        .unwrap_or(false)
+}
+
+pub fn is_sts_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().eq_ignore_ascii_case("sts"))
+        .unwrap_or(false)
+}
+
+/// 把 STS 文件反向转换为 XDTS（输出 `<源文件名>.xdts`）
+pub fn sts_to_xdts(
+    input_path: &Path,
+    output_dir: Option<&Path>,
+    quiet: bool,
+    opts: &Options,
+    cancel: &Cancellation,
+) -> Result<ProcessOutcome> {
+    let ts = converter::load_sts(input_path)?;
+
+    let output_dir = match output_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => input_path
+            .parent()
+            .context("无法获取父目录")?
+            .to_path_buf(),
+    };
+    let output_path = output_dir.join(format!(
+        "{}.xdts",
+        input_path.file_stem().unwrap().to_string_lossy()
+    ));
+
+    let root = converter::timesheet_to_xdts(&ts);
+    cancel.check()?;
+    xdts::save_xdts(&root, xdts::DEFAULT_COMMENT, &output_path, opts.atomic, opts.buffer_size())?;
+
+    if !quiet {
+        println!(
+            "STS {} → {} ({} 层, {} 帧)",
+            input_path.display(),
+            output_path.display(),
+            ts.layers.len(),
+            ts.frame_count
+        );
+    }
+
+    Ok(ProcessOutcome {
+        output_paths: vec![output_path],
+        ..Default::default()
+    })
+}
+
+/// 列出并解包 .stsx 容器中的所有 STS 文件
+fn extract_container(
+    input_path: &Path,
+    output_dir: Option<&Path>,
+    quiet: bool,
+    cancel: &Cancellation,
+) -> Result<ProcessOutcome> {
+    let entries = container::load_container(input_path)?;
+
+    let output_dir = match output_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => input_path
+            .parent()
+            .context("无法获取父目录")?
+            .to_path_buf(),
+    };
+
+    if !quiet {
+        println!("容器 {} 包含 {} 个 STS:", input_path.display(), entries.len());
+    }
+
+    let mut output_paths = Vec::new();
+    for entry in &entries {
+        // 只取文件名部分，防止条目名称指向输出目录之外
+        let file_name = Path::new(&entry.name)
+            .file_name()
+            .with_context(|| format!("无效的条目名称: '{}'", entry.name))?;
+        let output_path = output_dir.join(file_name);
+
+        cancel.check()?;
+        std::fs::write(&output_path, &entry.data)
+            .with_context(|| format!("无法创建文件: {}", output_path.display()))?;
+
+        if !quiet {
+            println!("  - {} ({} 字节)", entry.name, format_number(entry.data.len() as u64));
+        }
+        output_paths.push(output_path);
+    }
+
+    Ok(ProcessOutcome {
+        output_paths,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_table_name_is_cut_on_char_boundary() {
+        let ts = types::Timesheet {
+            name: format!("cut->{}", "あ".repeat(40)),
+            frame_count: 1,
+            layers: Vec::new(),
+            version: None,
+            cut: None,
+            comment: None,
+        };
+        let name = output_file_name(Path::new("cut.tdts"), 2, 1, &ts, &Options::default());
+        // "cut->" 占 5 字节，"あ" 为 3 字节：第 100 字节落在字符中间
+        assert_eq!(name, format!("cut_001_cut->{}.sts", "あ".repeat(31)));
+    }
+
+    #[test]
+    fn clashing_output_paths_are_numbered_across_sources() {
+        let outputs = OutputPaths::default();
+        let dir = Path::new("out");
+        assert_eq!(outputs.claim(dir, "cut.sts"), "cut.sts");
+        // 另一个源文件的输出与之重名（不区分大小写）
+        assert_eq!(outputs.clone().claim(dir, "CUT.sts"), "CUT (2).sts");
+        assert_eq!(outputs.claim(dir, "cut.sts"), "cut (3).sts");
+        assert_eq!(outputs.claim(Path::new("other"), "cut.sts"), "cut.sts");
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// 每帧的像素宽度
+const FRAME_WIDTH: usize = 2;
+/// 每层的像素高度
+const LAYER_HEIGHT: usize = 12;
+/// 层与层之间的分隔线颜色
+const SEPARATOR: [u8; 3] = [0xC0, 0xC0, 0xC0];
+/// 空白 cell 的颜色
+const BLANK: [u8; 3] = [0xFF, 0xFF, 0xFF];
+
+/// 将展开后的 cell 数据渲染为 PNG：横轴为帧，纵轴为层，颜色由 cel 编号决定
+pub fn save_preview_png(all_layers_cells: &[Vec<u32>], frame_count: usize, output_path: &Path) -> Result<()> {
+    let width = (frame_count * FRAME_WIDTH).max(1);
+    let height = (all_layers_cells.len() * LAYER_HEIGHT).max(1);
+
+    let mut pixels = vec![0xFFu8; width * height * 3];
+
+    for (layer_idx, cells) in all_layers_cells.iter().enumerate() {
+        for y in 0..LAYER_HEIGHT {
+            let row = layer_idx * LAYER_HEIGHT + y;
+            for (frame_idx, &cell) in cells.iter().enumerate() {
+                // 每层最后一行画分隔线
+                let color = if y == LAYER_HEIGHT - 1 {
+                    SEPARATOR
+                } else {
+                    cell_color(cell)
+                };
+                for x in 0..FRAME_WIDTH {
+                    let offset = (row * width + frame_idx * FRAME_WIDTH + x) * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    let file = File::create(output_path)
+        .with_context(|| format!("无法创建文件: {}", output_path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder
+        .write_header()
+        .with_context(|| format!("写入 PNG 失败: {}", output_path.display()))?;
+    writer
+        .write_image_data(&pixels)
+        .with_context(|| format!("写入 PNG 失败: {}", output_path.display()))?;
+
+    Ok(())
+}
+
+/// 根据 cel 编号生成颜色：相邻编号的色相相差较大，便于区分
+fn cell_color(cell: u32) -> [u8; 3] {
+    if cell == 0 {
+        return BLANK;
+    }
+
+    // 黄金角分布色相
+    let hue = (cell as f32 * 137.508) % 360.0;
+    hsv_to_rgb(hue, 0.55, 0.85)
+}
+
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [u8; 3] {
+    let c = value * saturation;
+    let x = c * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = value - c;
+
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    [
+        ((r + m) * 255.0) as u8,
+        ((g + m) * 255.0) as u8,
+        ((b + m) * 255.0) as u8,
+    ]
+}
@@ -0,0 +1,61 @@
+//! 文本导出中记录的来源信息（`--embed-provenance`）
+
+use serde::Serialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 导出文件的来源
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    /// 源文件路径
+    pub source: String,
+    /// 时间表名称
+    pub timesheet: String,
+    /// 转换时间 (UTC, RFC 3339)
+    pub converted_at: String,
+}
+
+impl Provenance {
+    pub fn new(source: &Path, timesheet: &str) -> Self {
+        Provenance {
+            source: source.display().to_string(),
+            timesheet: timesheet.to_string(),
+            converted_at: format_utc(SystemTime::now()),
+        }
+    }
+
+    /// CSV 等文本格式开头的注释行
+    pub fn comment_line(&self) -> String {
+        format!(
+            "# source: {}; timesheet: {}; converted: {}",
+            self.source, self.timesheet, self.converted_at
+        )
+    }
+}
+
+/// 格式化为 "2024-01-02T03:04:05Z"
+fn format_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // 由 1970-01-01 起的天数推算公历日期
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
@@ -0,0 +1,125 @@
+//! 断点续转的状态文件（`--state-file`）
+//!
+//! 每行记录一个已成功转换的源文件：`<修改时间 (Unix 毫秒)>\t<绝对路径>`。
+//! 与 `--overwrite-if-newer` 一样按修改时间判断：源文件在记录之后被修改时不再视为已完成。
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+pub struct StateFile {
+    path: PathBuf,
+    /// 已完成的源文件 → 记录时的修改时间
+    done: HashMap<PathBuf, u128>,
+    /// 以追加方式打开，每完成一个文件写入一行
+    file: Mutex<File>,
+}
+
+impl StateFile {
+    /// 读取已有的记录（文件不存在时为空），并以追加方式打开
+    pub fn open(path: &Path) -> Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("无法读取状态文件: {}", path.display()))
+            }
+        };
+
+        // 中断时可能只写了一半的最后一行（没有换行结尾）不采用；格式不对的行也忽略
+        let complete = match content.rfind('\n') {
+            Some(end) => &content[..=end],
+            None => "",
+        };
+        let done = complete
+            .lines()
+            .filter_map(|line| {
+                let (modified, source) = line.split_once('\t')?;
+                Some((PathBuf::from(source), modified.parse().ok()?))
+            })
+            .collect();
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("无法打开状态文件: {}", path.display()))?;
+        // 先结束写了一半的行，之后追加的记录从新的一行开始
+        if complete.len() < content.len() {
+            (&file)
+                .write_all(b"\n")
+                .with_context(|| format!("写入状态文件失败: {}", path.display()))?;
+        }
+
+        Ok(StateFile {
+            path: path.to_path_buf(),
+            done,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// 源文件是否已在之前的运行中完成，且之后没有被修改
+    pub fn is_done(&self, source: &Path) -> bool {
+        let key = state_key(source);
+        match (self.done.get(&key), modified_millis(source)) {
+            (Some(&recorded), Some(modified)) => modified <= recorded,
+            _ => false,
+        }
+    }
+
+    /// 记录一个已完成的源文件，立即写入磁盘
+    pub fn mark_done(&self, source: &Path) -> Result<()> {
+        let modified = modified_millis(source).unwrap_or(0);
+        let line = format!("{}\t{}\n", modified, state_key(source).display());
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .with_context(|| format!("写入状态文件失败: {}", self.path.display()))
+    }
+}
+
+/// 记录使用的路径：尽量取绝对路径，同一文件以不同相对路径传入时也能匹配
+fn state_key(source: &Path) -> PathBuf {
+    std::fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf())
+}
+
+fn modified_millis(path: &Path) -> Option<u128> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reopen_ignores_partial_last_line() {
+        let dir = std::env::temp_dir().join(format!("xdts2sts-state-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.xdts"), dir.join("b.xdts"));
+        std::fs::write(&a, "{}").unwrap();
+        std::fs::write(&b, "{}").unwrap();
+
+        // a 的记录完整；b 的记录写到一半时中断，没有换行
+        let state_path = dir.join("state.txt");
+        let line = |path: &Path| format!("{}\t{}", modified_millis(path).unwrap(), state_key(path).display());
+        std::fs::write(&state_path, format!("{}\n{}", line(&a), line(&b))).unwrap();
+
+        let state = StateFile::open(&state_path).unwrap();
+        assert!(state.is_done(&a));
+        assert!(!state.is_done(&b));
+        state.mark_done(&b).unwrap();
+        drop(state);
+
+        let state = StateFile::open(&state_path).unwrap();
+        assert!(state.is_done(&a));
+        assert!(state.is_done(&b));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
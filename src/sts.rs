@@ -0,0 +1,215 @@
+//! 读取 STS 文件
+
+use crate::converter::{has_footer_crc, FOOTER_CRC_FLAG, FOOTER_CRC_SIZE, HEADER_SIZE};
+use crate::options::CellWidth;
+use anyhow::{Context, Result};
+use encoding_rs::SHIFT_JIS;
+use std::path::Path;
+
+/// STS 文件中的一层
+#[derive(Debug, Clone)]
+pub struct StsLayer {
+    pub name: String,
+    /// 逐帧的 cel 编号，0 为空白
+    pub cells: Vec<u32>,
+}
+
+/// 解析后的 STS 文件
+#[derive(Debug, Clone)]
+pub struct StsFile {
+    /// 文件头中的帧数（没有层时也保留）
+    pub frame_count: usize,
+    pub layers: Vec<StsLayer>,
+}
+
+/// 读取并解析 STS 文件
+pub fn load_sts(path: &Path) -> Result<StsFile> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("无法读取文件: {}", path.display()))?;
+    parse_sts(&data)
+        .map_err(|e| anyhow::anyhow!("不是有效的 STS 文件: {}\n{}", path.display(), e))
+}
+
+/// 解析 STS 数据：校验文件头，按 cell 宽度标志读取帧数据区，按名称字段宽度读取层名称；
+/// 带 CRC32 尾部时先校验
+pub fn parse_sts(data: &[u8]) -> Result<StsFile> {
+    if data.len() < HEADER_SIZE {
+        anyhow::bail!("文件过短 ({} 字节)，不足 {} 字节的文件头", data.len(), HEADER_SIZE);
+    }
+    if data[0] != 0x11 || &data[1..18] != b"ShiraheiTimeSheet" {
+        anyhow::bail!("文件头标识不匹配 (应为 0x11 + \"ShiraheiTimeSheet\")");
+    }
+
+    let data = if has_footer_crc(data) {
+        check_footer_crc(data)?
+    } else {
+        data
+    };
+
+    let layer_count = data[18] as usize;
+    let frame_count = u16::from_le_bytes([data[19], data[20]]) as usize;
+    let cell_width = CellWidth::from_header_flag(data[21] & !FOOTER_CRC_FLAG)
+        .with_context(|| format!("未知的 cell 宽度标志: {}", data[21]))?;
+    let name_field_width = (data[22] != 0).then_some(data[22]);
+
+    // === 帧数据区 ===
+    let layer_size = frame_count * cell_width.bytes();
+    let names_start = HEADER_SIZE + layer_count * layer_size;
+    if data.len() < names_start {
+        anyhow::bail!(
+            "帧数据区不完整: {} 层 × {} 帧需要 {} 字节，实际只有 {} 字节",
+            layer_count,
+            frame_count,
+            names_start,
+            data.len()
+        );
+    }
+
+    let mut layer_cells: Vec<Vec<u32>> = data[HEADER_SIZE..names_start]
+        .chunks_exact(layer_size.max(1))
+        .map(|layer_data| {
+            layer_data
+                .chunks_exact(cell_width.bytes())
+                .map(|bytes| match cell_width {
+                    CellWidth::U8 => bytes[0] as u32,
+                    CellWidth::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+                    CellWidth::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                })
+                .collect()
+        })
+        .collect();
+    // 帧数为 0 时帧数据区为空
+    layer_cells.resize(layer_count, Vec::new());
+
+    // === 层名称区 ===
+    let mut offset = names_start;
+    let mut layers = Vec::with_capacity(layer_count);
+    for (idx, cells) in layer_cells.into_iter().enumerate() {
+        let name_bytes = match name_field_width {
+            // 固定宽度：去掉末尾的空格填充
+            Some(width) => {
+                let field = data.get(offset..offset + width as usize);
+                offset += width as usize;
+                field.map(|field| {
+                    let len = field.iter().rposition(|&b| b != b' ').map_or(0, |pos| pos + 1);
+                    &field[..len]
+                })
+            }
+            None => data.get(offset).and_then(|&len| {
+                let start = offset + 1;
+                offset = start + len as usize;
+                data.get(start..offset)
+            }),
+        }
+        .with_context(|| format!("层名称区不完整: 第{}层名称超出文件末尾", idx + 1))?;
+
+        let (name, _) = SHIFT_JIS.decode_without_bom_handling(name_bytes);
+        layers.push(StsLayer {
+            name: name.into_owned(),
+            cells,
+        });
+    }
+
+    Ok(StsFile { frame_count, layers })
+}
+
+/// 校验 CRC32 尾部，返回去掉尾部后的数据
+fn check_footer_crc(data: &[u8]) -> Result<&[u8]> {
+    if data.len() < HEADER_SIZE + FOOTER_CRC_SIZE {
+        anyhow::bail!("文件头标明有 CRC32 尾部，但文件只有 {} 字节", data.len());
+    }
+    let (body, footer) = data.split_at(data.len() - FOOTER_CRC_SIZE);
+    let expected = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    let actual = crc32fast::hash(body);
+    if actual != expected {
+        anyhow::bail!(
+            "CRC32 校验失败: 尾部记录 {:08x}，实际为 {:08x}，文件可能已损坏",
+            expected,
+            actual
+        );
+    }
+    Ok(body)
+}
+
+/// 回读刚写入的 STS 文件，确认层名称和逐帧数据与写入的数据一致
+pub fn verify_written(path: &Path, expected: &[u8]) -> Result<()> {
+    let written = parse_sts(expected)?;
+    let actual = load_sts(path)?;
+
+    if actual.layers.len() != written.layers.len() {
+        anyhow::bail!(
+            "回读校验失败: 写入 {} 层，读回 {} 层",
+            written.layers.len(),
+            actual.layers.len()
+        );
+    }
+
+    let describe = |cell: Option<&u32>| cell.map_or("无".to_string(), |c| c.to_string());
+    for (idx, (written, actual)) in written.layers.iter().zip(&actual.layers).enumerate() {
+        if written.name != actual.name {
+            anyhow::bail!(
+                "回读校验失败: 第{}层名称写入 '{}'，读回 '{}'",
+                idx + 1,
+                written.name,
+                actual.name
+            );
+        }
+        let frames = written.cells.len().max(actual.cells.len());
+        if let Some(frame) = (0..frames).find(|&f| written.cells.get(f) != actual.cells.get(f)) {
+            anyhow::bail!(
+                "回读校验失败: 第{}层 '{}' 帧 {} 写入 {}，读回 {}",
+                idx + 1,
+                written.name,
+                frame + 1,
+                describe(written.cells.get(frame)),
+                describe(actual.cells.get(frame))
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::encode_sts;
+    use crate::options::Options;
+    use crate::types::{Frame, Layer, Timesheet};
+    use crate::warning::Warnings;
+
+    #[test]
+    fn footer_crc_rejects_any_flipped_byte() {
+        let frame = |frame: u32, cell: u32| Frame {
+            frame,
+            cell,
+            explicit_null: false,
+            data: Vec::new(),
+            source: None,
+        };
+        let ts = Timesheet {
+            name: "t->T".to_string(),
+            frame_count: 4,
+            layers: vec![Layer {
+                name: "A".to_string(),
+                track_no: 0,
+                frames: vec![frame(0, 1), frame(2, 2)],
+            }],
+            version: None,
+            cut: None,
+            comment: None,
+        };
+        let opts = Options {
+            footer_crc: true,
+            ..Options::default()
+        };
+        let (data, _) = encode_sts(&ts, false, &opts, &mut Warnings::default()).unwrap();
+        assert_eq!(parse_sts(&data).unwrap().layers[0].cells, [1, 1, 2, 2]);
+
+        for i in 0..data.len() {
+            let mut corrupted = data.clone();
+            corrupted[i] ^= 0x01;
+            assert!(parse_sts(&corrupted).is_err(), "第 {} 字节被改动后仍通过校验", i);
+        }
+    }
+}
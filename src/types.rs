@@ -1,54 +1,120 @@
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// 源文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Xdts,
+    Tdts,
+}
+
+impl SourceFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceFormat::Xdts => "XDTS",
+            SourceFormat::Tdts => "TDTS",
+        }
+    }
+}
+
+impl std::str::FromStr for SourceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "xdts" => Ok(SourceFormat::Xdts),
+            "tdts" => Ok(SourceFormat::Tdts),
+            _ => anyhow::bail!("无效的输入格式: {} (可选 xdts|tdts)", s),
+        }
+    }
+}
 
 /// 关键帧结构
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct Frame {
+    /// 帧号（从 0 开始）
     pub frame: u32,
-    pub cell: u16,
+    /// cel 编号，0 为空白；保持到下一个关键帧
+    pub cell: u32,
+    /// 源数据中为 `SYMBOL_NULL_CELL`（显式空白），而非未曝光的空白
+    pub explicit_null: bool,
+    /// 该帧在源数据中的全部 data 项；只有一个值（即 cel 本身）时为空
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<DataValues>,
+    /// 源数据中的原始值（含 `SYMBOL_TICK_1` 等记号），仅在 `--preserve-symbols` 或
+    /// `--explain` 时记录；补在帧 0 的空白关键帧没有
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// 源数据中的一个 data 项
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DataValues {
+    pub id: Option<u32>,
+    pub values: Vec<String>,
 }
 
 /// 层结构
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct Layer {
     pub name: String,
+    /// 源数据中的 trackNo，层被重排、去重或反转后仍可据此对应回源文件
+    pub track_no: usize,
+    /// 按帧号升序排列的关键帧
     pub frames: Vec<Frame>,
 }
 
 /// 摄影表结构
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct Timesheet {
+    /// 时间表名称，格式为 `源文件名->时间表名`
     pub name: String,
+    /// 时长（帧数）
     pub frame_count: u32,
     pub layers: Vec<Layer>,
+    /// 源文件根对象的 `version` 字段
+    pub version: Option<u32>,
+    /// TDTS 中所属 cut 的名称；XDTS 为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cut: Option<String>,
+    /// XDTS 源文件的首行注释，重新导出 XDTS 时写回
+    #[serde(skip)]
+    pub comment: Option<String>,
 }
 
-// ========== JSON 解析用的结构体 ==========
+// ========== JSON 解析（及 XDTS 写出）用的结构体 ==========
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct XDTSRoot {
-    #[serde(rename = "timeTables")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<Header>,
+    #[serde(rename = "timeTables", deserialize_with = "deserialize_one_or_many")]
     pub time_tables: Vec<TimeTable>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct TDTSRoot {
-    #[serde(rename = "timeSheets")]
+    #[serde(rename = "timeSheets", deserialize_with = "deserialize_one_or_many")]
     pub time_sheets: Vec<TimeSheet>,
+    #[serde(default)]
+    pub version: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct TimeSheet {
     pub header: Header,
-    #[serde(rename = "timeTables")]
+    #[serde(rename = "timeTables", deserialize_with = "deserialize_one_or_many")]
     pub time_tables: Vec<TimeTable>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Header {
     pub cut: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TimeTable {
     pub name: String,
     pub duration: u32,
@@ -58,34 +124,211 @@ pub struct TimeTable {
     pub time_table_headers: Vec<TimeTableHeader>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Field {
-    #[serde(rename = "fieldId")]
-    pub field_id: u32,
+    #[serde(rename = "fieldId", default, skip_serializing_if = "Option::is_none")]
+    pub field_id: Option<u32>,
+    /// 部分导出工具用字符串名称 ("Cells", "Dialog") 标识 field
+    #[serde(rename = "fieldName", default, skip_serializing_if = "Option::is_none")]
+    pub field_name: Option<String>,
     pub tracks: Vec<Track>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TimeTableHeader {
-    #[serde(rename = "fieldId")]
-    pub field_id: u32,
+    #[serde(rename = "fieldId", default, skip_serializing_if = "Option::is_none")]
+    pub field_id: Option<u32>,
+    #[serde(rename = "fieldName", default, skip_serializing_if = "Option::is_none")]
+    pub field_name: Option<String>,
+    #[serde(default)]
     pub names: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Track {
     #[serde(rename = "trackNo")]
     pub track_no: usize,
+    /// 部分导出工具把层名称写在 track 上（`label` 或 `name`），header 中没有 names 时使用
+    #[serde(default, alias = "name", skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
     pub frames: Vec<FrameData>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FrameData {
     pub frame: u32,
     pub data: Vec<DataItem>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DataItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(deserialize_with = "deserialize_values")]
     pub values: Vec<String>,
 }
+
+/// 单个值：部分导出工具会把 cel 编号写成 JSON 数字而不是字符串，
+/// 或写成 `{ "name": "A12", "type": "cell" }` 这样的对象
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawValue {
+    Scalar(RawScalar),
+    Object {
+        #[serde(default)]
+        name: Option<RawScalar>,
+        #[serde(default)]
+        value: Option<RawScalar>,
+    },
+}
+
+/// 字符串或数字
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawScalar {
+    Str(String),
+    Num(serde_json::Number),
+}
+
+impl RawScalar {
+    fn into_string(self) -> String {
+        match self {
+            RawScalar::Str(s) => s,
+            RawScalar::Num(n) => n.to_string(),
+        }
+    }
+}
+
+/// 反序列化 `values`，数字统一转为字符串形式，对象取 `name`（没有时取 `value`）
+fn deserialize_values<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = Vec::<RawValue>::deserialize(deserializer)?;
+    raw.into_iter()
+        .map(|value| match value {
+            RawValue::Scalar(scalar) => Ok(scalar.into_string()),
+            RawValue::Object { name, value } => name
+                .or(value)
+                .map(RawScalar::into_string)
+                .ok_or_else(|| serde::de::Error::custom("值对象中没有 name 或 value 字段")),
+        })
+        .collect()
+}
+
+/// 单个对象或对象数组：部分导出工具只有一个时间表时直接写对象而不是数组
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    Many(Vec<T>),
+    One(T),
+}
+
+/// 反序列化单个对象或数组，统一为数组
+fn deserialize_one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(items) => items,
+        OneOrMany::One(item) => vec![item],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_values_parse_to_cells() {
+        let json = r#"{
+            "timeTables": [{
+                "name": "T",
+                "duration": 6,
+                "fields": [{"fieldId": 0, "tracks": [{"trackNo": 0, "frames": [
+                    {"frame": 0, "data": [{"id": 0, "values": [12]}]},
+                    {"frame": 2, "data": [{"id": 0, "values": [3]}]},
+                    {"frame": 4, "data": [{"id": 0, "values": [7.5]}]}
+                ]}]}],
+                "timeTableHeaders": [{"fieldId": 0, "names": ["A"]}]
+            }],
+            "version": 5
+        }"#;
+        let root: XDTSRoot = serde_json::from_str(json).unwrap();
+        let values: Vec<&str> = root.time_tables[0].fields[0].tracks[0]
+            .frames
+            .iter()
+            .map(|f| f.data[0].values[0].as_str())
+            .collect();
+        assert_eq!(values, ["12", "3", "7.5"]);
+
+        let ts = crate::parser::load_xdts(
+            std::path::Path::new("t.xdts"),
+            json,
+            &crate::options::Options::default(),
+            &mut crate::warning::Warnings::default(),
+        )
+        .unwrap()
+        .remove(0);
+        let cells: Vec<(u32, u32)> = ts.layers[0].frames.iter().map(|f| (f.frame, f.cell)).collect();
+        assert_eq!(cells[..2], [(0, 12), (2, 3)]);
+    }
+
+    const TABLE: &str = r#"{
+        "name": "T",
+        "duration": 4,
+        "fields": [{"fieldId": 4, "tracks": [{"trackNo": 0, "frames": [
+            {"frame": 0, "data": [{"values": ["1"]}]}
+        ]}]}],
+        "timeTableHeaders": [{"fieldId": 4, "names": ["A"]}]
+    }"#;
+
+    #[test]
+    fn single_time_table_equals_array() {
+        let one: XDTSRoot =
+            serde_json::from_str(&format!(r#"{{"timeTables": {}}}"#, TABLE)).unwrap();
+        let many: XDTSRoot =
+            serde_json::from_str(&format!(r#"{{"timeTables": [{}]}}"#, TABLE)).unwrap();
+        assert_eq!(one.time_tables.len(), 1);
+        assert_eq!(format!("{:?}", one), format!("{:?}", many));
+    }
+
+    #[test]
+    fn single_time_sheet_equals_array() {
+        let sheet = format!(r#"{{"header": {{"cut": "1"}}, "timeTables": {}}}"#, TABLE);
+        let one: TDTSRoot =
+            serde_json::from_str(&format!(r#"{{"timeSheets": {}}}"#, sheet)).unwrap();
+        let many: TDTSRoot =
+            serde_json::from_str(&format!(r#"{{"timeSheets": [{}]}}"#, sheet)).unwrap();
+        assert_eq!(one.time_sheets.len(), 1);
+        assert_eq!(one.time_sheets[0].time_tables.len(), 1);
+        assert_eq!(format!("{:?}", one), format!("{:?}", many));
+    }
+
+    fn values(json: &str) -> Result<Vec<String>, serde_json::Error> {
+        serde_json::from_str::<DataItem>(&format!(r#"{{"values": {}}}"#, json)).map(|d| d.values)
+    }
+
+    #[test]
+    fn string_value_is_kept() {
+        assert_eq!(values(r#"["A12"]"#).unwrap(), ["A12"]);
+    }
+
+    #[test]
+    fn object_value_uses_name_then_value() {
+        assert_eq!(values(r#"[{"value": 5}]"#).unwrap(), ["5"]);
+        assert_eq!(values(r#"[{"value": "A3"}]"#).unwrap(), ["A3"]);
+        assert_eq!(
+            values(r#"[{"name": "A12", "value": 3, "type": "cell"}]"#).unwrap(),
+            ["A12"]
+        );
+    }
+
+    #[test]
+    fn object_value_without_name_or_value_is_error() {
+        let err = values(r#"[{"type": "cell"}]"#).unwrap_err();
+        assert!(err.to_string().contains("没有 name 或 value"), "{}", err);
+        assert!(values(r#"[{"value": [1]}]"#).is_err());
+    }
+}
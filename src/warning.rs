@@ -0,0 +1,381 @@
+use crate::converter::format_spans;
+use crate::types::SourceFormat;
+use std::fmt;
+
+/// 转换过程中产生的警告
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// `--field-name` 指定的 field 不存在，已回退到 fieldId 匹配
+    FieldNameNotFound { table: String, field_name: String },
+    /// trackNo 远超名称列表长度
+    TrackNoOutOfRange {
+        table: String,
+        track_no: usize,
+        names_len: usize,
+    },
+    /// 非整数 cel 值已按策略取整
+    FractionalCell { token: String, cell: u32 },
+    /// 层名称包含无法编码为 Shift-JIS 的字符
+    UnencodableName { name: String },
+    /// 层名称超过名称字段上限（默认 255 字节）被截断
+    NameTruncated { name: String, limit: usize },
+    /// cel 编号按 `--cel-base` 偏移后超出 u32 范围
+    CelOutOfRange { layer: String, cell: u32 },
+    /// cel 编号按 `--cell-offset` 偏移后小于 1，已写为空白
+    CelUnderflow { layer: String, cell: u32, offset: i32 },
+    /// 层的关键帧超出时间表时长，超出部分被裁掉
+    FramesClipped {
+        table: String,
+        layer: String,
+        frames: Vec<u32>,
+        frame_count: u32,
+    },
+    /// 层的内容远早于时间表时长结束
+    SpanTooShort {
+        table: String,
+        layer: String,
+        end_frame: u32,
+        frame_count: u32,
+    },
+    /// 源文件的格式版本未知，按默认方式解析
+    UnknownVersion { format: SourceFormat, version: u32 },
+    /// 时间表的层与 `--layer-order-from` 列表不一致
+    LayerOrderMismatch {
+        table: String,
+        /// 列表中有但时间表中不存在的层
+        missing: Vec<String>,
+        /// 时间表中有但列表中没有的层（已排在最后）
+        unlisted: Vec<String>,
+    },
+    /// `--keep-layers` 列出的层在时间表中不存在
+    KeptLayersMissing { table: String, missing: Vec<String> },
+    /// 无法解析为 cel 的帧（记号等）被跳过或当作空白（仅 `--strict` 时检查）
+    FramesSkipped {
+        table: String,
+        layer: String,
+        tokens: Vec<String>,
+    },
+    /// cel 所在 field 之外的 field 中有数据，转换时被忽略（仅 `--strict` 时检查）
+    FieldsIgnored { table: String, fields: Vec<String> },
+    /// cel 编号后的延长保持标记被去掉，只按普通 cel 处理（仅 `--strict` 时检查）
+    HoldMarkersDropped {
+        table: String,
+        layer: String,
+        tokens: Vec<String>,
+    },
+    /// 同一 cut 内各时间表的时长相差超过容差
+    CutDurationMismatch {
+        cut: String,
+        /// (时间表名称, 时长)
+        durations: Vec<(String, u32)>,
+    },
+    /// 层的逐帧数据与另一层完全相同，已被 `--dedupe-layers` 移除
+    DuplicateLayer {
+        table: String,
+        layer: String,
+        same_as: String,
+    },
+    /// cel 编号超过 `--max-cell`，已截断（只记录该层第一个超限的帧）
+    CellClamped {
+        table: String,
+        layer: String,
+        /// 帧号（从 0 开始，显示时加 1）
+        frame: usize,
+        cell: u32,
+        max_cell: u32,
+    },
+    /// `--retime` 取整后多个关键帧落在同一帧，只保留了最后一个
+    RetimeCollision {
+        table: String,
+        layer: String,
+        frame: u32,
+    },
+    /// 层的曝光中间有空白间隙
+    ExposureGaps {
+        table: String,
+        layer: String,
+        /// (起始帧, 结束帧)
+        gaps: Vec<(usize, usize)>,
+    },
+    /// 层的关键帧数超过帧数的 `--dense-layer-threshold` 比例
+    DenseLayer {
+        table: String,
+        layer: String,
+        keyframes: usize,
+        frame_count: u32,
+    },
+    /// 名称不匹配 `--only-tables` 而被跳过的时间表
+    TablesFiltered { pattern: String, skipped: usize },
+    /// 预览图生成失败（不影响 STS 输出）
+    PreviewFailed { message: String },
+    /// cut 代码在 `--cut-map` 对照表中不存在，保留原始代码
+    CutUnmapped { cut: String },
+    /// 输出文件名与本次运行中已生成的文件（同一源文件或其他源文件）重复，已自动改名
+    OutputRenamed {
+        table: String,
+        name: String,
+        renamed: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::FieldNameNotFound { table, field_name } => write!(
+                f,
+                "时间表 '{}' 中没有名为 '{}' 的 field，改用 fieldId 匹配",
+                table, field_name
+            ),
+            Warning::TrackNoOutOfRange {
+                table,
+                track_no,
+                names_len,
+            } => write!(
+                f,
+                "时间表 '{}' 的 trackNo {} 远超名称列表长度 {}，数据可能已损坏",
+                table, track_no, names_len
+            ),
+            Warning::FractionalCell { token, cell } => {
+                write!(f, "非整数 cel 值 '{}' 已转换为 {}", token, cell)
+            }
+            Warning::UnencodableName { name } => {
+                write!(f, "层名称 '{}' 包含无法编码为Shift-JIS的字符", name)
+            }
+            Warning::NameTruncated { name, limit } => {
+                write!(f, "层名称过长，截断到{}字节以内: '{}'", limit, name)
+            }
+            Warning::CelOutOfRange { layer, cell } => write!(
+                f,
+                "层 '{}' 的 cel {} 偏移后超出范围，已写为 {}",
+                layer,
+                cell,
+                u32::MAX
+            ),
+            Warning::CelUnderflow {
+                layer,
+                cell,
+                offset,
+            } => write!(
+                f,
+                "层 '{}' 的 cel {} 偏移 {} 后小于 1，已写为空白",
+                layer, cell, offset
+            ),
+            Warning::FramesClipped {
+                table,
+                layer,
+                frames,
+                frame_count,
+            } => write!(
+                f,
+                "时间表 '{}' 的层 '{}' 在第 {} 帧有关键帧，超出时长 {} 帧，已被裁掉",
+                table,
+                layer,
+                frames
+                    .iter()
+                    .map(|frame| frame.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                frame_count
+            ),
+            Warning::SpanTooShort {
+                table,
+                layer,
+                end_frame,
+                frame_count,
+            } => write!(
+                f,
+                "时间表 '{}' 的层 '{}' 的内容在第 {} 帧结束，远早于时长 {} 帧",
+                table, layer, end_frame, frame_count
+            ),
+            Warning::UnknownVersion { format, version } => write!(
+                f,
+                "未知的 {} 格式版本 {}，按默认方式解析",
+                format.name(),
+                version
+            ),
+            Warning::LayerOrderMismatch {
+                table,
+                missing,
+                unlisted,
+            } => {
+                write!(f, "时间表 '{}' 的层与层顺序列表不一致", table)?;
+                if !missing.is_empty() {
+                    write!(f, "；不存在: {}", missing.join(", "))?;
+                }
+                if !unlisted.is_empty() {
+                    write!(f, "；未列出（已排在最后）: {}", unlisted.join(", "))?;
+                }
+                Ok(())
+            }
+            Warning::KeptLayersMissing { table, missing } => write!(
+                f,
+                "时间表 '{}' 中不存在层名单中的层: {}",
+                table,
+                missing.join(", ")
+            ),
+            Warning::FramesSkipped {
+                table,
+                layer,
+                tokens,
+            } => {
+                let mut distinct = tokens.clone();
+                distinct.sort();
+                distinct.dedup();
+                write!(
+                    f,
+                    "时间表 '{}' 层 '{}' 有 {} 个帧无法解析为 cel: {}",
+                    table,
+                    layer,
+                    tokens.len(),
+                    distinct.join(", ")
+                )
+            }
+            Warning::FieldsIgnored { table, fields } => {
+                write!(f, "时间表 '{}' 中以下 field 的数据被忽略: {}", table, fields.join(", "))
+            }
+            Warning::HoldMarkersDropped {
+                table,
+                layer,
+                tokens,
+            } => {
+                let mut distinct = tokens.clone();
+                distinct.sort();
+                distinct.dedup();
+                write!(
+                    f,
+                    "时间表 '{}' 层 '{}' 有 {} 个帧的延长保持标记被忽略: {}",
+                    table,
+                    layer,
+                    tokens.len(),
+                    distinct.join(", ")
+                )
+            }
+            Warning::CutDurationMismatch { cut, durations } => write!(
+                f,
+                "cut '{}' 内各时间表的时长不一致: {}",
+                cut,
+                durations
+                    .iter()
+                    .map(|(name, duration)| format!("'{}' {} 帧", name, duration))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Warning::DuplicateLayer {
+                table,
+                layer,
+                same_as,
+            } => write!(
+                f,
+                "时间表 '{}' 的层 '{}' 与层 '{}' 完全相同，已移除",
+                table, layer, same_as
+            ),
+            Warning::CellClamped {
+                table,
+                layer,
+                frame,
+                cell,
+                max_cell,
+            } => write!(
+                f,
+                "时间表 '{}' 的层 '{}' 从第 {} 帧起有 cel 超过上限 {}（如 {}），已截断为 {}",
+                table,
+                layer,
+                frame + 1,
+                max_cell,
+                cell,
+                max_cell
+            ),
+            Warning::RetimeCollision {
+                table,
+                layer,
+                frame,
+            } => write!(
+                f,
+                "时间表 '{}' 的层 '{}' 重新计时后有多个关键帧落在第 {} 帧，只保留最后一个",
+                table, layer, frame
+            ),
+            Warning::ExposureGaps { table, layer, gaps } => write!(
+                f,
+                "时间表 '{}' 的层 '{}' 中间有空白: 帧 {}",
+                table,
+                layer,
+                format_spans(gaps)
+            ),
+            Warning::DenseLayer {
+                table,
+                layer,
+                keyframes,
+                frame_count,
+            } => write!(
+                f,
+                "时间表 '{}' 的层 '{}' 关键帧过密: {} 帧中有 {} 个关键帧",
+                table, layer, frame_count, keyframes
+            ),
+            Warning::TablesFiltered { pattern, skipped } => {
+                write!(f, "{} 个时间表名称不匹配 '{}'，已跳过", skipped, pattern)
+            }
+            Warning::PreviewFailed { message } => write!(f, "生成预览图失败: {}", message),
+            Warning::CutUnmapped { cut } => {
+                write!(f, "cut '{}' 不在 cut 对照表中，保留原始名称", cut)
+            }
+            Warning::OutputRenamed {
+                table,
+                name,
+                renamed,
+            } => write!(
+                f,
+                "时间表 '{}' 的输出文件名 '{}' 与本次运行中已生成的文件重复，已改为 '{}'",
+                table, name, renamed
+            ),
+        }
+    }
+}
+
+/// 警告收集器，在解析和转换过程中传递
+#[derive(Debug, Clone, Default)]
+pub struct Warnings {
+    items: Vec<Warning>,
+}
+
+impl Warning {
+    /// 是否意味着输出丢失了源数据中的信息（`--strict` 时视为错误）
+    pub fn is_data_loss(&self) -> bool {
+        matches!(
+            self,
+            Warning::FractionalCell { .. }
+                | Warning::UnencodableName { .. }
+                | Warning::NameTruncated { .. }
+                | Warning::CelOutOfRange { .. }
+                | Warning::CelUnderflow { .. }
+                | Warning::FramesClipped { .. }
+                | Warning::CellClamped { .. }
+                | Warning::RetimeCollision { .. }
+                | Warning::FramesSkipped { .. }
+                | Warning::FieldsIgnored { .. }
+                | Warning::HoldMarkersDropped { .. }
+        )
+    }
+}
+
+impl Warnings {
+    pub fn push(&mut self, warning: Warning) {
+        self.items.push(warning);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Warning> {
+        self.items.iter()
+    }
+
+    /// 并入另一组警告（如工作线程中收集的），保持顺序
+    pub fn extend(&mut self, other: Warnings) {
+        self.items.extend(other.items);
+    }
+}
@@ -0,0 +1,101 @@
+//! 由解析后的时间表重建 XDTS 文件
+
+use crate::converter::write_output;
+use crate::parser::NULL_CELL;
+use crate::types::*;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// XDTS 文件的默认首行注释，与 OpenToonz 导出的一致
+pub const DEFAULT_COMMENT: &str = "exchangeDigitalTimeSheet Save Data";
+
+/// 写出的 XDTS 版本
+pub const XDTS_VERSION: u32 = 5;
+
+/// XDTS 中 cel 所在 field 的 fieldId
+const XDTS_CELL_FIELD_ID: u32 = 0;
+
+/// 把同一 cut 的时间表重建为一个 XDTS 根对象，每个时间表对应一个 timeTable
+pub fn timesheets_to_xdts(cut: &str, timesheets: &[&Timesheet]) -> XDTSRoot {
+    XDTSRoot {
+        header: Some(Header { cut: cut.to_string() }),
+        time_tables: timesheets.iter().map(|ts| timesheet_to_table(ts)).collect(),
+        version: Some(XDTS_VERSION),
+    }
+}
+
+fn timesheet_to_table(timesheet: &Timesheet) -> TimeTable {
+    // 名称为 "源文件名->cut->时间表名"，只取最后一段
+    let name = timesheet
+        .name
+        .rsplit_once("->")
+        .map_or(timesheet.name.as_str(), |(_, table)| table);
+
+    let tracks = timesheet
+        .layers
+        .iter()
+        .enumerate()
+        .map(|(track_no, layer)| Track {
+            track_no,
+            label: None,
+            frames: layer.frames.iter().map(frame_data).collect(),
+        })
+        .collect();
+
+    TimeTable {
+        name: name.to_string(),
+        duration: timesheet.frame_count,
+        fields: vec![Field {
+            field_id: Some(XDTS_CELL_FIELD_ID),
+            field_name: None,
+            tracks,
+        }],
+        time_table_headers: vec![TimeTableHeader {
+            field_id: Some(XDTS_CELL_FIELD_ID),
+            field_name: None,
+            names: timesheet.layers.iter().map(|layer| layer.name.clone()).collect(),
+        }],
+    }
+}
+
+/// 关键帧还原为 data 项：保留源数据中的全部 data，否则按 cel 编号生成
+fn frame_data(frame: &Frame) -> FrameData {
+    let data = if frame.data.is_empty() {
+        let value = if frame.cell == 0 {
+            NULL_CELL.to_string()
+        } else {
+            frame.cell.to_string()
+        };
+        vec![DataItem {
+            id: Some(XDTS_CELL_FIELD_ID),
+            values: vec![value],
+        }]
+    } else {
+        frame
+            .data
+            .iter()
+            .map(|item| DataItem {
+                id: item.id.or(Some(XDTS_CELL_FIELD_ID)),
+                values: item.values.clone(),
+            })
+            .collect()
+    };
+
+    FrameData {
+        frame: frame.frame,
+        data,
+    }
+}
+
+/// 写出 XDTS 文件：首行为注释，第二行为 JSON
+pub fn save_xdts(
+    root: &XDTSRoot,
+    comment: &str,
+    output_path: &Path,
+    atomic: bool,
+    buffer_size: usize,
+) -> Result<()> {
+    let json = serde_json::to_string(root).context("序列化 XDTS 失败")?;
+    let text = format!("{}\n{}", comment, json);
+    write_output(output_path, text.as_bytes(), atomic, buffer_size)
+}